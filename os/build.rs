@@ -4,10 +4,14 @@ use std::io::{Result, Write};
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    println!("cargo:rerun-if-changed={}", LINUX_PATH);
     insert_app_data().unwrap();
 }
 
 static TARGET_PATH: &str = "../user/target/riscv64gc-unknown-none-elf/release/";
+/// statically linked Linux programs, which run with the Linux personality,
+/// built from ../user/linux/ by `make linux` or copied there
+static LINUX_PATH: &str = "../user/target/linux/";
 
 fn insert_app_data() -> Result<()> {
     let mut f = File::create("src/link_app.S").unwrap();
@@ -17,9 +21,16 @@ fn insert_app_data() -> Result<()> {
         .map(|dir_entry| {
             let mut name_with_ext = dir_entry.unwrap().file_name().into_string().unwrap();
             name_with_ext.drain(name_with_ext.find('.').unwrap()..name_with_ext.len());
-            name_with_ext
+            (name_with_ext, TARGET_PATH, false)
         })
         .collect();
+    // the directory is optional
+    if let Ok(dir) = read_dir(LINUX_PATH) {
+        apps.extend(dir.map(|dir_entry| {
+            let name = dir_entry.unwrap().file_name().into_string().unwrap();
+            (name, LINUX_PATH, true)
+        }));
+    }
     apps.sort();
 
     writeln!(
@@ -38,7 +49,27 @@ _num_app:
     }
     writeln!(f, r#"    .quad app_{}_end"#, apps.len() - 1)?;
 
-    for (idx, app) in apps.iter().enumerate() {
+    writeln!(
+        f,
+        r#"
+    .global _app_names
+_app_names:"#
+    )?;
+    for (app, _, _) in apps.iter() {
+        writeln!(f, r#"    .string "{}""#, app)?;
+    }
+
+    writeln!(
+        f,
+        r#"
+    .global _app_linux
+_app_linux:"#
+    )?;
+    for (_, _, linux) in apps.iter() {
+        writeln!(f, r#"    .byte {}"#, *linux as u8)?;
+    }
+
+    for (idx, (app, path, _)) in apps.iter().enumerate() {
         println!("app_{}: {}", idx, app);
         writeln!(
             f,
@@ -50,7 +81,7 @@ _num_app:
 app_{0}_start:
     .incbin "{2}{1}"
 app_{0}_end:"#,
-            idx, app, TARGET_PATH
+            idx, app, path
         )?;
     }
     Ok(())
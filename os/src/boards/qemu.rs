@@ -2,7 +2,12 @@
 
 pub const CLOCK_FREQ: usize = 12500000;
 pub const MEMORY_END: usize = 0x8800_0000;
+/// goldfish real time clock, counting nanoseconds since the unix epoch
+pub const RTC_BASE: usize = 0x0010_1000;
 
 pub const MMIO: &[(usize, usize)] = &[
     (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
+    (0x1000_1000, 0x00_1000), // Virtio Block in virt machine
 ];
+
+pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
@@ -5,14 +5,28 @@ pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
+/// number of pages in the swap area at the start of the block device
+pub const SWAP_PAGES: usize = 16384;
+/// syscall ids below this are counted per task
+pub const MAX_SYSCALL_NUM: usize = 500;
+/// priority of a new task, used by the stride scheduler
+pub const DEFAULT_PRIORITY: usize = 16;
 
+/// width of virtual addresses, 39 for sv39 or 48 for sv48 with the `sv48` feature
+#[cfg(not(feature = "sv48"))]
+pub const VA_WIDTH: usize = 39;
+#[cfg(feature = "sv48")]
+pub const VA_WIDTH: usize = 48;
+/// number of page table levels of the paging mode
+pub const PAGE_LEVELS: usize = (VA_WIDTH - PAGE_SIZE_BITS) / 9;
+/// MODE field of satp for the paging mode
+pub const SATP_MODE: usize = PAGE_LEVELS + 5;
+
+/// end of the lower half of the address space, user mappings stay below it
+pub const USER_SPACE_END: usize = 1 << (VA_WIDTH - 1);
+
+/// the highest page of the upper half of the address space, whatever its width
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
-/// Return (bottom, top) of a kernel stack in kernel space.
-pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
-    let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
-    let bottom = top - KERNEL_STACK_SIZE;
-    (bottom, top)
-}
 
-pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
+pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO, RTC_BASE};
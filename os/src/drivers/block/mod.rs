@@ -0,0 +1,26 @@
+//! Block devices, used as the swap area
+
+mod virtio_blk;
+
+pub use virtio_blk::VirtIOBlock;
+
+use crate::board::BlockDeviceImpl;
+use alloc::sync::Arc;
+use core::any::Any;
+use lazy_static::*;
+
+/// size of a block in bytes
+pub const BLOCK_SZ: usize = 512;
+
+/// a device which reads and writes whole blocks
+pub trait BlockDevice: Send + Sync + Any {
+    /// read block `block_id` into `buf`
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    /// write `buf` into block `block_id`
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+}
+
+lazy_static! {
+    /// the block device of the board
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+}
@@ -0,0 +1,79 @@
+//! Block device on the virtio-mmio bus of the qemu virt machine
+
+use super::BlockDevice;
+use crate::mm::{
+    frame_alloc_contiguous, kernel_token, FrameRangeTracker, PageTable, PhysAddr, PhysPageNum,
+    VirtAddr,
+};
+use crate::utils::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
+
+const VIRTIO0: usize = 0x10001000;
+
+/// virtio-blk device, accessed by polling
+pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static, VirtioHal>>);
+
+lazy_static! {
+    /// frames of the virtqueues, which live as long as the device
+    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameRangeTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.0
+            .exclusive_access()
+            .read_block(block_id, buf)
+            .expect("Error when reading VirtIOBlk");
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.0
+            .exclusive_access()
+            .write_block(block_id, buf)
+            .expect("Error when writing VirtIOBlk");
+    }
+}
+
+impl VirtIOBlock {
+    pub fn new() -> Self {
+        unsafe {
+            Self(UPSafeCell::new(
+                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
+            ))
+        }
+    }
+}
+
+/// memory management for the virtio driver
+pub struct VirtioHal;
+
+impl Hal for VirtioHal {
+    fn dma_alloc(pages: usize) -> usize {
+        let frames = frame_alloc_contiguous(pages, 1).unwrap();
+        let pa: PhysAddr = frames.ppn.into();
+        QUEUE_FRAMES.exclusive_access().push(frames);
+        pa.0
+    }
+
+    fn dma_dealloc(pa: usize, pages: usize) -> i32 {
+        let ppn_base: PhysPageNum = PhysAddr::from(pa).into();
+        // dropping the tracker gives the frames back
+        QUEUE_FRAMES
+            .exclusive_access()
+            .retain(|frames| frames.ppn != ppn_base || frames.pages != pages);
+        0
+    }
+
+    fn phys_to_virt(addr: usize) -> usize {
+        addr
+    }
+
+    fn virt_to_phys(vaddr: usize) -> usize {
+        // buffers may be on a kernel stack, which is not identically mapped
+        PageTable::from_token(kernel_token())
+            .translate_va(VirtAddr::from(vaddr))
+            .unwrap()
+            .0
+    }
+}
@@ -0,0 +1,5 @@
+//! Device drivers
+
+pub mod block;
+
+pub use block::{BLOCK_DEVICE, BLOCK_SZ};
@@ -1,5 +1,8 @@
 //! Loading user applications into memory
 
+use alloc::vec::Vec;
+use lazy_static::*;
+
 /// Get the total number of applications.
 pub fn get_num_app() -> usize {
     extern "C" {
@@ -24,3 +27,61 @@ pub fn get_app_data(app_id: usize) -> &'static [u8] {
         )
     }
 }
+
+lazy_static! {
+    /// names of all applications, in the same order as their data
+    static ref APP_NAMES: Vec<&'static str> = {
+        let num_app = get_num_app();
+        extern "C" {
+            fn _app_names();
+        }
+        let mut start = _app_names as usize as *const u8;
+        let mut v = Vec::new();
+        unsafe {
+            for _ in 0..num_app {
+                let mut end = start;
+                while end.read_volatile() != b'\0' {
+                    end = end.add(1);
+                }
+                let slice = core::slice::from_raw_parts(start, end as usize - start as usize);
+                let str = core::str::from_utf8(slice).unwrap();
+                v.push(str);
+                start = end.add(1);
+            }
+        }
+        v
+    };
+}
+
+/// get applications data by its name
+pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
+    APP_NAMES
+        .iter()
+        .position(|&app_name| app_name == name)
+        .map(get_app_data)
+}
+
+/// whether the application `name` is a Linux program, which runs with the Linux personality
+pub fn is_linux_app(name: &str) -> bool {
+    extern "C" {
+        fn _app_linux();
+    }
+    APP_NAMES
+        .iter()
+        .position(|&app_name| app_name == name)
+        .map_or(false, |app_id| unsafe {
+            (_app_linux as usize as *const u8)
+                .add(app_id)
+                .read_volatile()
+                != 0
+        })
+}
+
+/// print the names of all applications
+pub fn list_apps() {
+    println!("/**** APPS ****");
+    for app in APP_NAMES.iter() {
+        println!("{}", app);
+    }
+    println!("**************/");
+}
@@ -33,6 +33,7 @@ mod board;
 #[macro_use]
 mod console;
 mod config;
+mod drivers;
 mod lang_items;
 mod loader;
 mod mm;
@@ -65,7 +66,11 @@ pub fn rust_main() -> ! {
     println!("[kernel] Hello, world!");
     mm::init();
     println!("[kernel] back to world!");
+    let frames = mm::frame_stats();
+    println!("[kernel] {} of {} frames free", frames.free, frames.total);
     mm::remap_test();
+    mm::buddy_allocator_test();
+    loader::list_apps();
     trap::init();
     //trap::enable_interrupt();
     trap::enable_timer_interrupt();
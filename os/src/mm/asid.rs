@@ -0,0 +1,105 @@
+//! Address space identifiers (ASIDs) of [`PageTable`](super::PageTable)s.
+//!
+//! TLB entries are tagged with the ASID in `satp`, so switching between
+//! address spaces needs no `sfence.vma`. ASID 0 belongs to the kernel space,
+//! user spaces get the others in order. When they run out, a new generation
+//! starts with a full TLB flush and every user space gets a new ASID the next
+//! time it is activated. A hart without ASIDs gives every space ASID 0, and
+//! the trampoline flushes the whole TLB whenever it changes `satp` to or from
+//! a token with ASID 0 of a user space.
+
+use crate::utils::UPSafeCell;
+use core::arch::asm;
+use lazy_static::*;
+use riscv::register::satp;
+
+/// mask of the ASID field of `satp`, which starts at bit 44
+pub const ASID_MASK: usize = 0xffff;
+
+/// an ASID and the generation it was allocated in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Asid {
+    pub id: usize,
+    generation: usize,
+}
+
+impl Asid {
+    /// the ASID of the kernel space, which is never allocated.
+    /// a new page table has it until it is activated as a user space
+    pub const KERNEL: Self = Self {
+        id: 0,
+        generation: 0,
+    };
+}
+
+struct AsidAllocator {
+    generation: usize,
+    next: usize,
+    /// the largest ASID supported by the hart
+    max: usize,
+}
+
+impl AsidAllocator {
+    fn new() -> Self {
+        Self {
+            generation: 1,
+            next: 1,
+            max: 0,
+        }
+    }
+    /// return `asid` if it is of the current generation, otherwise a new one
+    fn refresh(&mut self, asid: Asid) -> Asid {
+        if asid.generation == self.generation {
+            return asid;
+        }
+        if self.max == 0 {
+            return Asid {
+                id: 0,
+                generation: self.generation,
+            };
+        }
+        if self.next > self.max {
+            // rollover, entries of the last generation must not hit any more
+            self.generation += 1;
+            self.next = 1;
+            unsafe {
+                asm!("sfence.vma");
+            }
+        }
+        let id = self.next;
+        self.next += 1;
+        Asid {
+            id,
+            generation: self.generation,
+        }
+    }
+}
+
+lazy_static! {
+    static ref ASID_ALLOCATOR: UPSafeCell<AsidAllocator> =
+        unsafe { UPSafeCell::new(AsidAllocator::new()) };
+}
+
+/// find out how many ASID bits the hart implements, must run in the kernel space
+pub fn init() {
+    let kernel_satp = satp::read().bits();
+    // unimplemented bits of the ASID field are hardwired to zero
+    let max = unsafe {
+        satp::write(kernel_satp | ASID_MASK << 44);
+        let max = satp::read().bits() >> 44 & ASID_MASK;
+        satp::write(kernel_satp);
+        asm!("sfence.vma");
+        max
+    };
+    ASID_ALLOCATOR.exclusive_access().max = max;
+    if max == 0 {
+        println!("[kernel] no ASIDs, the TLB is flushed on every switch");
+    } else {
+        println!("[kernel] {} ASIDs for user spaces", max);
+    }
+}
+
+/// return `asid` if it is still valid, otherwise allocate a new one
+pub fn refresh_asid(asid: Asid) -> Asid {
+    ASID_ALLOCATOR.exclusive_access().refresh(asid)
+}
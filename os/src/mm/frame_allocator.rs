@@ -4,6 +4,8 @@
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
 use crate::utils::UPSafeCell;
+use alloc::collections::BTreeSet;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
@@ -36,55 +38,154 @@ impl Drop for FrameTracker {
     }
 }
 
+/// manage `pages` contiguous frames which have the same lifecycle as the tracker
+pub struct FrameRangeTracker {
+    pub ppn: PhysPageNum,
+    pub pages: usize,
+}
+
+impl FrameRangeTracker {
+    pub fn new(ppn: PhysPageNum, pages: usize) -> Self {
+        // page cleaning
+        for i in 0..pages {
+            PhysPageNum(ppn.0 + i).get_bytes_array().fill(0);
+        }
+        Self { ppn, pages }
+    }
+}
+
+impl Debug for FrameRangeTracker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "FrameRangeTracker:PPN={:#x},pages={}",
+            self.ppn.0, self.pages
+        ))
+    }
+}
+
+impl Drop for FrameRangeTracker {
+    fn drop(&mut self) {
+        FRAME_ALLOCATOR
+            .exclusive_access()
+            .dealloc_contiguous(self.ppn, self.pages);
+    }
+}
+
+/// numbers of frames managed by the frame allocator
+#[derive(Copy, Clone, Debug)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+}
+
 trait FrameAllocator {
     fn new() -> Self;
+    fn init(&mut self, l: PhysPageNum, r: PhysPageNum);
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    /// allocate `pages` contiguous frames, the first of which is aligned to `align` frames
+    fn alloc_contiguous(&mut self, pages: usize, align: usize) -> Option<PhysPageNum>;
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, pages: usize);
+    fn stats(&self) -> FrameStats;
 }
 
-/// an implementation for frame allocator
-pub struct StackFrameAllocator {
-    current: usize,
+/// number of block sizes, the largest block has `1 << (MAX_ORDER - 1)` frames
+const MAX_ORDER: usize = 20;
+
+/// a buddy system frame allocator. A free block of order `k` consists of
+/// `1 << k` frames and is aligned to its size, so that the buddy it was split
+/// from and may be merged with again is found by flipping bit `k` of its ppn.
+pub struct BuddyFrameAllocator {
+    start: usize,
     end: usize,
-    recycled: Vec<usize>,
+    /// first frames of the free blocks of every order
+    free_lists: [BTreeSet<usize>; MAX_ORDER],
+    /// length of the allocation starting at every frame, 0 if none starts there
+    allocated: Vec<u32>,
+    free: usize,
 }
 
-impl StackFrameAllocator {
-    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
-        self.end = r.0;
+impl BuddyFrameAllocator {
+    /// give `[l, r)` back as the largest aligned blocks, merging them with their buddies
+    fn free_range(&mut self, mut l: usize, r: usize) {
+        while l < r {
+            let mut order = (l.trailing_zeros() as usize).min(MAX_ORDER - 1);
+            while l + (1 << order) > r {
+                order -= 1;
+            }
+            self.free_block(l, order);
+            l += 1 << order;
+        }
+    }
+    fn free_block(&mut self, mut ppn: usize, mut order: usize) {
+        self.free += 1 << order;
+        while order + 1 < MAX_ORDER && self.free_lists[order].remove(&(ppn ^ (1 << order))) {
+            ppn &= !(1 << order);
+            order += 1;
+        }
+        self.free_lists[order].insert(ppn);
     }
 }
-impl FrameAllocator for StackFrameAllocator {
+
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
+            start: 0,
             end: 0,
-            recycled: Vec::new(),
+            free_lists: core::array::from_fn(|_| BTreeSet::new()),
+            allocated: Vec::new(),
+            free: 0,
         }
     }
+    fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.start = l.0;
+        self.end = r.0;
+        self.allocated = vec![0; r.0 - l.0];
+        self.free_range(l.0, r.0);
+    }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else if self.current == self.end {
-            None
-        } else {
-            self.current += 1;
-            Some((self.current - 1).into())
-        }
+        self.alloc_contiguous(1, 1)
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
+        self.dealloc_contiguous(ppn, 1);
+    }
+    fn alloc_contiguous(&mut self, pages: usize, align: usize) -> Option<PhysPageNum> {
+        assert!(pages > 0 && align.is_power_of_two());
+        let order = pages.next_power_of_two().max(align).trailing_zeros() as usize;
+        let found = (order..MAX_ORDER).find(|&k| !self.free_lists[k].is_empty())?;
+        let ppn = self.free_lists[found].pop_first().unwrap();
+        // split the block, keeping the lower half each time
+        for k in (order..found).rev() {
+            self.free_lists[k].insert(ppn + (1 << k));
+        }
+        self.free -= 1 << order;
+        // the frames of the block after `pages` are not needed
+        self.free_range(ppn + pages, ppn + (1 << order));
+        self.allocated[ppn - self.start] = pages as u32;
+        Some(ppn.into())
+    }
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, pages: usize) {
         let ppn = ppn.0;
-        // validity check
-        if ppn >= self.current || self.recycled.iter().any(|&v| v == ppn) {
-            panic!("Frame ppn={:#x} has not been allocated!", ppn);
+        // validity check, which also catches a double free
+        if ppn < self.start || ppn >= self.end || self.allocated[ppn - self.start] as usize != pages
+        {
+            panic!(
+                "Frames ppn={:#x} pages={} have not been allocated!",
+                ppn, pages
+            );
+        }
+        self.allocated[ppn - self.start] = 0;
+        self.free_range(ppn, ppn + pages);
+    }
+    fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.end - self.start,
+            free: self.free,
         }
-        // recycle
-        self.recycled.push(ppn);
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 lazy_static! {
     /// frame allocator instance through lazy_static!
@@ -116,6 +217,19 @@ fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
 
+/// allocate `pages` contiguous frames, the first of which is aligned to `align` frames
+pub fn frame_alloc_contiguous(pages: usize, align: usize) -> Option<FrameRangeTracker> {
+    FRAME_ALLOCATOR
+        .exclusive_access()
+        .alloc_contiguous(pages, align)
+        .map(|ppn| FrameRangeTracker::new(ppn, pages))
+}
+
+/// numbers of total and free frames
+pub fn frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.exclusive_access().stats()
+}
+
 #[allow(unused)]
 /// a simple test for frame allocator
 pub fn frame_allocator_test() {
@@ -134,3 +248,46 @@ pub fn frame_allocator_test() {
     drop(v);
     println!("frame_allocator_test passed!");
 }
+
+/// a test for the buddy system, on frames which are not really there
+/// and the frame allocator of the kernel
+pub fn buddy_allocator_test() {
+    let mut allocator = BuddyFrameAllocator::new();
+    // 64 frames aligned to 64 and 8 more
+    allocator.init(PhysPageNum(0x1000), PhysPageNum(0x1048));
+    assert_eq!(allocator.stats().total, 72);
+    assert_eq!(allocator.stats().free, 72);
+    assert!(allocator.free_lists[6].contains(&0x1000));
+    assert!(allocator.free_lists[3].contains(&0x1040));
+    // one frame splits the smallest free block, which is the one of 8 frames
+    let a = allocator.alloc().unwrap();
+    assert_eq!(a.0, 0x1040);
+    assert!((0..3).all(|k| allocator.free_lists[k].contains(&(0x1040 + (1 << k)))));
+    assert_eq!(allocator.stats().free, 71);
+    // freeing it merges the halves back
+    allocator.dealloc(a);
+    assert!(allocator.free_lists[3].contains(&0x1040));
+    assert!((0..3).all(|k| allocator.free_lists[k].is_empty()));
+    // 3 frames aligned to 16 come from the block of 64, the frame after them stays free
+    let b = allocator.alloc_contiguous(3, 16).unwrap();
+    assert_eq!(b.0 % 16, 0);
+    assert_eq!(allocator.stats().free, 69);
+    assert!(allocator.free_lists[0].contains(&(b.0 + 3)));
+    // the lowest block of 8 frames is the one left over from that split
+    let c = allocator.alloc_contiguous(8, 8).unwrap();
+    assert_eq!(c.0, 0x1008);
+    assert!(allocator.alloc_contiguous(64, 1).is_none());
+    allocator.dealloc_contiguous(b, 3);
+    allocator.dealloc_contiguous(c, 8);
+    assert_eq!(allocator.stats().free, 72);
+    assert!(allocator.free_lists[6].contains(&0x1000));
+    assert!(allocator.free_lists[3].contains(&0x1040));
+    // the kernel's frame allocator counts contiguous frames as well
+    let free = frame_stats().free;
+    let range = frame_alloc_contiguous(5, 4).unwrap();
+    assert_eq!(range.ppn.0 % 4, 0);
+    assert_eq!(frame_stats().free, free - 5);
+    drop(range);
+    assert_eq!(frame_stats().free, free);
+    println!("buddy_allocator_test passed!");
+}
@@ -1,12 +1,17 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
 
-use super::{frame_alloc, FrameTracker};
-use super::{PTEFlags, PageTable, PageTableEntry};
+use super::shm::ShmSegment;
+use super::swap::SwapSlot;
+use super::{frame_alloc, frame_stats, FrameTracker};
+use super::{level_pages, PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use crate::config::{
+    MEMORY_END, MMIO, PAGE_LEVELS, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_SPACE_END,
+    USER_STACK_SIZE,
+};
 use crate::utils::UPSafeCell;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::arch::asm;
@@ -29,57 +34,433 @@ extern "C" {
 lazy_static! {
     /// a memory set instance through lazy_static! managing kernel space
     pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
-        Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+        Arc::new(unsafe {
+            UPSafeCell::new(MemorySet::new_kernel().expect("no memory for the kernel space"))
+        });
+    /// the token of the kernel space, which is read while the kernel space is borrowed,
+    /// like by the virtio driver when a kernel stack is mapped
+    static ref KERNEL_TOKEN: usize = KERNEL_SPACE.exclusive_access().token();
+}
+
+/// activate the kernel space and keep its token
+pub fn init_kernel_space() {
+    KERNEL_SPACE.exclusive_access().activate();
+    lazy_static::initialize(&KERNEL_TOKEN);
+}
+
+/// the token of the kernel space
+pub fn kernel_token() -> usize {
+    *KERNEL_TOKEN
+}
+
+/// visits the user spaces of the tasks which are not in use until `f` returns true,
+/// return whether it did
+pub type UserSpaces = fn(&mut dyn FnMut(&mut MemorySet) -> bool) -> bool;
+
+lazy_static! {
+    /// how the clock finds the user spaces, none before the tasks are created
+    static ref USER_SPACES: UPSafeCell<Option<UserSpaces>> = unsafe { UPSafeCell::new(None) };
+    /// root of the page table of the space a page was swapped out of last
+    static ref CLOCK_SPACE: UPSafeCell<usize> = unsafe { UPSafeCell::new(0) };
+}
+
+/// let the clock algorithm swap out pages of the user spaces `user_spaces` visits
+pub fn register_user_spaces(user_spaces: UserSpaces) {
+    *USER_SPACES.exclusive_access() = Some(user_spaces);
+}
+
+/// Evict a page with the clock algorithm, which sweeps the private resident pages
+/// of `current` and of every user space not in use, starting with the spaces after
+/// the one a page was swapped out of last. A page whose accessed bit is set gets a
+/// second chance, so the second round finds one. Return false if nothing could be
+/// swapped out.
+fn swap_out_one(mut current: Option<&mut MemorySet>) -> bool {
+    let last = *CLOCK_SPACE.exclusive_access();
+    let user_spaces = *USER_SPACES.exclusive_access();
+    for _ in 0..2 {
+        for after_last in [true, false] {
+            let mut sweep =
+                |space: &mut MemorySet| (space.root() > last) == after_last && space.sweep();
+            if let Some(current) = current.as_deref_mut() {
+                if sweep(current) {
+                    return true;
+                }
+            }
+            if user_spaces.map_or(false, |visit| visit(&mut sweep)) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Swap out pages of `current` and of the user spaces which are not in use
+/// until `frames` frames are free or nothing more can be swapped out.
+fn reserve_frames(mut current: Option<&mut MemorySet>, frames: usize) {
+    while frame_stats().free < frames && swap_out_one(current.as_deref_mut()) {}
+}
+
+/// Allocate a frame, swapping out pages of the user spaces which are not in use
+/// if memory has run out.
+pub fn frame_alloc_or_swap() -> Option<FrameTracker> {
+    reserve_frames(None, 1);
+    frame_alloc()
+}
+
+/// Free `frames` frames by swapping out pages of the user spaces which are not
+/// in use. The kernel space itself never swaps, so this is done before borrowing
+/// it to map a kernel stack.
+pub fn reserve_kernel_frames(frames: usize) {
+    reserve_frames(None, frames);
+}
+
+/// about how many frames the page tables mapping `pages` pages take
+fn table_frames(pages: usize) -> usize {
+    pages / level_pages(1) + PAGE_LEVELS
+}
+
+/// where the entry and the program headers of an elf are in user space,
+/// which a Linux program finds in its auxiliary vector
+pub struct ElfInfo {
+    pub entry: usize,
+    pub phdr: usize,
+    pub phent: usize,
+    pub phnum: usize,
 }
 
 /// memory set structure, controls virtual-memory space
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// the page swapped out last by the clock algorithm
+    clock_hand: VirtPageNum,
+    /// where the heap area moved by sbrk starts
+    heap_start: Option<VirtPageNum>,
 }
 
 impl MemorySet {
-    pub fn new_bare() -> Self {
-        Self {
-            page_table: PageTable::new(),
+    pub fn new_bare() -> Option<Self> {
+        Some(Self {
+            page_table: PageTable::new()?,
             areas: Vec::new(),
-        }
+            clock_hand: VirtPageNum(0),
+            heap_start: None,
+        })
     }
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
-    /// Assume that no conflicts.
+    /// the token to activate this user space with, whose ASID is valid
+    pub fn user_token(&mut self) -> usize {
+        self.page_table.user_token()
+    }
+    /// Assume that no conflicts. Return None if there is no memory left.
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+    ) -> Option<()> {
         self.push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
+        )
+    }
+    /// Whether any page in `[start_vpn, end_vpn)` belongs to an area.
+    fn overlaps(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.areas
+            .iter()
+            .any(|area| area.overlaps(start_vpn, end_vpn))
+    }
+    /// Map a new framed area, return false if it overlaps an existing area.
+    pub fn mmap(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> bool {
+        if self.overlaps(start_va.floor(), end_va.ceil()) {
+            return false;
+        }
+        // a lazy area takes no frames until its pages are touched
+        self.push(
+            MapArea::new(start_va, end_va, MapType::Lazy, permission),
+            None,
+        )
+        .is_some()
+    }
+    /// Map a new framed area of `pages` pages at a free place chosen by the kernel,
+    /// return its start, or None if there is no room.
+    pub fn mmap_anywhere(&mut self, pages: usize, permission: MapPermission) -> Option<VirtAddr> {
+        let start_vpn = self.find_free_area(pages)?;
+        let end_vpn = VirtPageNum(start_vpn.0 + pages);
+        self.mmap(start_vpn.into(), end_vpn.into(), permission)
+            .then_some(start_vpn.into())
+    }
+    /// Find the highest `pages` pages of user space which are in no area.
+    fn find_free_area(&self, pages: usize) -> Option<VirtPageNum> {
+        let mut ranges: Vec<_> = self
+            .areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| (area.vpn_range.get_start().0, area.vpn_range.get_end().0))
+            .collect();
+        ranges.sort_unstable_by(|a, b| b.cmp(a));
+        let mut end = VirtAddr::from(USER_SPACE_END).floor().0;
+        for (area_start, area_end) in ranges {
+            if end >= area_end + pages {
+                break;
+            }
+            end = end.min(area_start);
+        }
+        // keep the page at address 0 unmapped
+        (end > pages).then(|| VirtPageNum(end - pages))
+    }
+    /// Map the shared memory segment `shm` at `start_va`, or at a free place
+    /// chosen by the kernel if it is None. Return the start of the new area,
+    /// or None if the range is taken or there is no memory left.
+    pub fn shm_attach(
+        &mut self,
+        shm: Arc<ShmSegment>,
+        start_va: Option<VirtAddr>,
+        permission: MapPermission,
+    ) -> Option<VirtAddr> {
+        let pages = shm.frames.len();
+        let start_vpn = match start_va {
+            Some(start_va) => start_va.floor(),
+            None => self.find_free_area(pages)?,
+        };
+        let end_vpn = VirtPageNum(start_vpn.0 + pages);
+        if end_vpn > VirtAddr::from(USER_SPACE_END).floor() || self.overlaps(start_vpn, end_vpn) {
+            return None;
+        }
+        let mut map_area = MapArea::new(
+            start_vpn.into(),
+            end_vpn.into(),
+            MapType::Shared,
+            permission,
         );
+        map_area.shm = Some(shm);
+        self.push_user(map_area, None, 0)?;
+        Some(start_vpn.into())
+    }
+    /// Unmap the shared memory area starting at `start_va`, false if there is none.
+    pub fn shm_detach(&mut self, start_va: VirtAddr) -> bool {
+        let start_vpn = start_va.floor();
+        if !self
+            .areas
+            .iter()
+            .any(|area| area.map_type == MapType::Shared && area.vpn_range.get_start() == start_vpn)
+        {
+            return false;
+        }
+        self.remove_area_with_start_vpn(start_vpn);
+        true
+    }
+    /// Split the areas `[start_vpn, end_vpn)` cuts through, so that every area is
+    /// either inside or outside of it, and take out the ones inside.
+    /// Return None and change nothing if some page in it is not in a user area or
+    /// in the heap, which sbrk only finds by its start.
+    fn take_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> Option<Vec<MapArea>> {
+        let in_heap = self.areas.iter().any(|area| {
+            Some(area.vpn_range.get_start()) == self.heap_start && area.overlaps(start_vpn, end_vpn)
+        });
+        if in_heap {
+            return None;
+        }
+        let all_mapped = VPNRange::new(start_vpn, end_vpn).into_iter().all(|vpn| {
+            self.areas
+                .iter()
+                .any(|area| area.map_perm.contains(MapPermission::U) && area.contains(vpn))
+        });
+        if !all_mapped {
+            return None;
+        }
+        let mut areas = Vec::new();
+        let mut taken = Vec::new();
+        for mut area in core::mem::take(&mut self.areas) {
+            if !area.overlaps(start_vpn, end_vpn) {
+                areas.push(area);
+                continue;
+            }
+            let mut middle = if area.vpn_range.get_start() < start_vpn {
+                let middle = area.split_off(start_vpn);
+                areas.push(area);
+                middle
+            } else {
+                area
+            };
+            if end_vpn < middle.vpn_range.get_end() {
+                areas.push(middle.split_off(end_vpn));
+            }
+            taken.push(middle);
+        }
+        self.areas = areas;
+        Some(taken)
+    }
+    /// Unmap `[start_va, end_va)`, splitting the areas it cuts through.
+    /// Return false and change nothing if some page in it is not in a user area
+    /// or in the heap.
+    pub fn munmap(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
+        match self.take_range(start_va.floor(), end_va.ceil()) {
+            Some(taken) => {
+                for mut area in taken {
+                    area.unmap(&mut self.page_table);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+    /// Change the permission of `[start_va, end_va)` to `permission`, splitting the
+    /// areas it cuts through. Return false and change nothing if some page in it is
+    /// not in a user area or in the heap.
+    pub fn mprotect(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> bool {
+        match self.take_range(start_va.floor(), end_va.ceil()) {
+            Some(taken) => {
+                for mut area in taken {
+                    area.set_permission(&mut self.page_table, permission);
+                    self.areas.push(area);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+    /// Allocate a zeroed frame for a lazy page on its first touch, read a swapped out
+    /// page back, or give a copy-on-write page a private copy on its first write.
+    /// Return false if `va` is outside every user area, the area does not allow
+    /// `access`, the fault is none of those or there is no memory left.
+    pub fn handle_page_fault(&mut self, va: VirtAddr, access: MapPermission) -> bool {
+        let vpn = va.floor();
+        let idx = match self.areas.iter().position(|area| {
+            area.contains(vpn) && area.map_perm.contains(access | MapPermission::U)
+        }) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        if self.translate(vpn).map_or(false, |pte| pte.is_valid()) {
+            if !access.contains(MapPermission::W) || !self.areas[idx].cow_pages.contains(&vpn) {
+                return false;
+            }
+            // only a frame which is still used by another space has to be copied
+            let frame = if self.areas[idx].is_shared(vpn) {
+                match self.alloc_frame() {
+                    Some(frame) => Some(frame),
+                    None => return false,
+                }
+            } else {
+                None
+            };
+            self.areas[idx].copy_on_write(&mut self.page_table, vpn, frame);
+            return true;
+        }
+        let area = &self.areas[idx];
+        if area.map_type != MapType::Lazy && !area.swapped.contains_key(&vpn) {
+            return false;
+        }
+        match self.alloc_frame() {
+            Some(frame) => self.areas[idx]
+                .map_frame(&mut self.page_table, vpn, frame)
+                .is_some(),
+            None => false,
+        }
+    }
+    /// Allocate a frame for a user page, first swapping out pages of this space
+    /// and the others until there are frames for the page and its page tables.
+    fn alloc_frame(&mut self) -> Option<FrameTracker> {
+        reserve_frames(Some(self), 1 + table_frames(1));
+        frame_alloc()
+    }
+    /// root of the page table, which tells the spaces apart
+    fn root(&self) -> usize {
+        self.page_table.root_ppn().0
+    }
+    /// Sweep the private resident user pages once from the clock hand on, clear
+    /// the accessed bits which are set, and swap out the first page whose bit is
+    /// clear. Return false if there is none or the swap area is full.
+    fn sweep(&mut self) -> bool {
+        let mut pages: Vec<(VirtPageNum, usize)> = self
+            .areas
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| area.map_perm.contains(MapPermission::U))
+            .flat_map(|(idx, area)| area.private_pages().map(move |vpn| (vpn, idx)))
+            .collect();
+        if pages.is_empty() {
+            return false;
+        }
+        pages.sort_unstable_by_key(|(vpn, _)| *vpn);
+        let start = pages
+            .iter()
+            .position(|(vpn, _)| *vpn > self.clock_hand)
+            .unwrap_or(0);
+        for i in 0..pages.len() {
+            let (vpn, idx) = pages[(start + i) % pages.len()];
+            if self.page_table.clear_accessed(vpn) {
+                continue;
+            }
+            self.clock_hand = vpn;
+            if !self.areas[idx].swap_out(&mut self.page_table, vpn) {
+                return false;
+            }
+            *CLOCK_SPACE.exclusive_access() = self.root();
+            return true;
+        }
+        false
+    }
+    /// Unmap and remove the area starting at `start_vpn`, if any.
+    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
+        if let Some((idx, area)) = self
+            .areas
+            .iter_mut()
+            .enumerate()
+            .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
+        {
+            area.unmap(&mut self.page_table);
+            self.areas.remove(idx);
+        }
+    }
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) -> Option<()> {
+        self.push_at(map_area, data, 0)
     }
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// Push a framed area with `data` placed `offset` bytes into its first page.
+    /// Return None and leave the space as it was if there is no memory left.
+    fn push_at(&mut self, mut map_area: MapArea, data: Option<&[u8]>, offset: usize) -> Option<()> {
+        if map_area.map(&mut self.page_table).is_none() {
+            map_area.unmap(&mut self.page_table);
+            return None;
+        }
         if let Some(data) = data {
-            map_area.copy_data(&mut self.page_table, data);
+            map_area.copy_data(&mut self.page_table, data, offset);
         }
         self.areas.push(map_area);
+        Some(())
+    }
+    /// Push an area of this user space like `push_at`, first swapping out pages of
+    /// this space and the others until there are frames to map it.
+    fn push_user(&mut self, map_area: MapArea, data: Option<&[u8]>, offset: usize) -> Option<()> {
+        reserve_frames(Some(self), map_area.frames_to_map());
+        self.push_at(map_area, data, offset)
     }
     /// Mention that trampoline is not collected by areas.
-    fn map_trampoline(&mut self) {
+    fn map_trampoline(&mut self) -> Option<()> {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
     /// Without kernel stacks.
-    pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+    pub fn new_kernel() -> Option<Self> {
+        let mut memory_set = Self::new_bare()?;
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
         // map kernel sections
         println!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
         println!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
@@ -97,7 +478,7 @@ impl MemorySet {
                 MapPermission::R | MapPermission::X,
             ),
             None,
-        );
+        )?;
         println!("mapping .rodata section");
         memory_set.push(
             MapArea::new(
@@ -107,7 +488,7 @@ impl MemorySet {
                 MapPermission::R,
             ),
             None,
-        );
+        )?;
         println!("mapping .data section");
         memory_set.push(
             MapArea::new(
@@ -117,7 +498,7 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )?;
         println!("mapping .bss section");
         memory_set.push(
             MapArea::new(
@@ -127,7 +508,7 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )?;
         println!("mapping physical memory");
         memory_set.push(
             MapArea::new(
@@ -137,7 +518,7 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )?;
         println!("mapping memory-mapped registers");
         for pair in MMIO {
             memory_set.push(
@@ -148,22 +529,31 @@ impl MemorySet {
                     MapPermission::R | MapPermission::W,
                 ),
                 None,
-            );
+            )?;
         }
-        memory_set
+        Some(memory_set)
     }
     /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
+    /// also returns user_sp and entry point, or None if there is no memory left.
+    pub fn from_elf(elf_data: &[u8]) -> Option<(Self, usize, ElfInfo)> {
+        // the root page table and the ones of the trampoline
+        reserve_frames(None, 1 + table_frames(1));
+        let mut memory_set = Self::new_bare()?;
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
         // map program headers of elf, with U flag
         let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
         let elf_header = elf.header;
         let magic = elf_header.pt1.magic;
         assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
         let ph_count = elf_header.pt2.ph_count();
+        let ph_offset = elf_header.pt2.ph_offset() as usize;
+        let mut info = ElfInfo {
+            entry: elf_header.pt2.entry_point() as usize,
+            phdr: 0,
+            phent: elf_header.pt2.ph_entry_size() as usize,
+            phnum: ph_count as usize,
+        };
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
@@ -183,10 +573,17 @@ impl MemorySet {
                 }
                 let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
                 max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
+                let offset = ph.offset() as usize;
+                let file_size = ph.file_size() as usize;
+                // the program headers are loaded with the segment covering them
+                if (offset..offset + file_size).contains(&ph_offset) {
+                    info.phdr = usize::from(start_va) + ph_offset - offset;
+                }
+                memory_set.push_user(
                     map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                    Some(&elf.input[offset..offset + file_size]),
+                    start_va.page_offset(),
+                )?;
             }
         }
         // map user stack with U flags
@@ -195,7 +592,7 @@ impl MemorySet {
         // guard page
         user_stack_bottom += PAGE_SIZE;
         let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
+        memory_set.push_user(
             MapArea::new(
                 user_stack_bottom.into(),
                 user_stack_top.into(),
@@ -203,19 +600,22 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
-        );
+            0,
+        )?;
         // used in sbrk
-        memory_set.push(
+        memory_set.heap_start = Some(VirtAddr::from(user_stack_top).floor());
+        memory_set.push_user(
             MapArea::new(
                 user_stack_top.into(),
                 user_stack_top.into(),
-                MapType::Framed,
+                MapType::Lazy,
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
-        );
+            0,
+        )?;
         // map TrapContext
-        memory_set.push(
+        memory_set.push_user(
             MapArea::new(
                 TRAP_CONTEXT.into(),
                 TRAMPOLINE.into(),
@@ -223,12 +623,53 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        (
-            memory_set,
-            user_stack_top,
-            elf.header.pt2.entry_point() as usize,
-        )
+            0,
+        )?;
+        Some((memory_set, user_stack_top, info))
+    }
+    /// Clone a user space. The frames of user areas are shared, writable ones
+    /// copy-on-write in both spaces, and only the trap context is copied at once.
+    /// Return None if there is no memory left.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> Option<MemorySet> {
+        // the page tables of the new space and the frame of its trap context
+        let frames = user_space
+            .areas
+            .iter()
+            .map(|area| match area.map_perm.contains(MapPermission::U) {
+                true => table_frames(area.data_frames.len()),
+                false => area.frames_to_map(),
+            })
+            .sum::<usize>()
+            + 1
+            + table_frames(1);
+        reserve_frames(Some(user_space), frames);
+        let mut memory_set = Self::new_bare()?;
+        memory_set.heap_start = user_space.heap_start;
+        // map trampoline
+        memory_set.map_trampoline()?;
+        for area in user_space.areas.iter_mut() {
+            let mut new_area = MapArea::from_another(area);
+            if area.map_perm.contains(MapPermission::U) {
+                // data sections/user_stack/heap/mmap
+                area.share_with(
+                    &mut new_area,
+                    &mut user_space.page_table,
+                    &mut memory_set.page_table,
+                )?;
+                memory_set.areas.push(new_area);
+                continue;
+            }
+            // the trap context is written by the kernel through its physical address
+            memory_set.push(new_area, None)?;
+            for vpn in area.resident_pages() {
+                let src_ppn = user_space.page_table.translate(vpn).unwrap().ppn();
+                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                dst_ppn
+                    .get_bytes_array()
+                    .copy_from_slice(src_ppn.get_bytes_array());
+            }
+        }
+        Some(memory_set)
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -253,27 +694,43 @@ impl MemorySet {
             false
         }
     }
+    /// Grow the area starting at `start`, return false if it would run into another area
+    /// or there is no memory left.
     #[allow(unused)]
     pub fn append_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
-        if let Some(area) = self
+        if let Some(idx) = self
             .areas
-            .iter_mut()
-            .find(|area| area.vpn_range.get_start() == start.floor())
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start.floor())
         {
-            area.append_to(&mut self.page_table, new_end.ceil());
-            true
+            if self.overlaps(self.areas[idx].vpn_range.get_end(), new_end.ceil()) {
+                return false;
+            }
+            self.areas[idx]
+                .append_to(&mut self.page_table, new_end.ceil())
+                .is_some()
         } else {
             false
         }
     }
+    /// Release all user pages but keep the page table, used when a task exits.
+    pub fn recycle_data_pages(&mut self) {
+        self.areas.clear();
+    }
 }
 
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    /// pages whose frame is shared and mapped read-only until the first write
+    cow_pages: BTreeSet<VirtPageNum>,
+    /// pages which are in the swap area instead of a frame
+    swapped: BTreeMap<VirtPageNum, Arc<SwapSlot>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// the segment a shared area maps
+    shm: Option<Arc<ShmSegment>>,
 }
 
 impl MapArea {
@@ -288,39 +745,250 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(start_vpn, end_vpn),
             data_frames: BTreeMap::new(),
+            cow_pages: BTreeSet::new(),
+            swapped: BTreeMap::new(),
             map_type,
             map_perm,
+            shm: None,
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let ppn: PhysPageNum;
+    pub fn from_another(another: &MapArea) -> Self {
+        Self {
+            vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
+            data_frames: BTreeMap::new(),
+            cow_pages: BTreeSet::new(),
+            swapped: BTreeMap::new(),
+            map_type: another.map_type,
+            map_perm: another.map_perm,
+            shm: another.shm.clone(),
+        }
+    }
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    pub fn overlaps(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() < end_vpn && start_vpn < self.vpn_range.get_end()
+    }
+    /// Keep `[start, at)` and return `[at, end)` together with its frames.
+    pub fn split_off(&mut self, at: VirtPageNum) -> Self {
+        let tail = Self {
+            vpn_range: VPNRange::new(at, self.vpn_range.get_end()),
+            data_frames: self.data_frames.split_off(&at),
+            cow_pages: self.cow_pages.split_off(&at),
+            swapped: self.swapped.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            shm: self.shm.clone(),
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), at);
+        tail
+    }
+    /// Map `vpn`, return None if there is no memory left.
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<()> {
         match self.map_type {
-            MapType::Identical => {
-                ppn = PhysPageNum(vpn.0);
+            MapType::Identical => page_table.map(vpn, PhysPageNum(vpn.0), self.pte_flags()),
+            MapType::Framed | MapType::Lazy => self.map_frame(page_table, vpn, frame_alloc()?),
+            MapType::Shared => unreachable!("a shared area is mapped as a whole"),
+        }
+    }
+    /// Map `vpn` to `frame`, reading the page back if it was swapped out.
+    /// Return None if there is no memory left for the page tables.
+    pub fn map_frame(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        frame: FrameTracker,
+    ) -> Option<()> {
+        page_table.map(vpn, frame.ppn, self.pte_flags())?;
+        if let Some(slot) = self.swapped.remove(&vpn) {
+            slot.read(frame.ppn);
+        }
+        self.data_frames.insert(vpn, Arc::new(frame));
+        Some(())
+    }
+    /// Write the page `vpn` to the swap area and free its frame,
+    /// return false if the swap area is full.
+    pub fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let slot = match SwapSlot::write(self.data_frames[&vpn].ppn) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        page_table.unmap(vpn);
+        // only private pages are swapped out, so the page is writable when it comes back
+        self.cow_pages.remove(&vpn);
+        self.data_frames.remove(&vpn);
+        self.swapped.insert(vpn, Arc::new(slot));
+        true
+    }
+    /// whether the frame of `vpn` is also used by another space
+    pub fn is_shared(&self, vpn: VirtPageNum) -> bool {
+        Arc::strong_count(&self.data_frames[&vpn]) > 1
+    }
+    /// resident pages whose frame is not shared with another space
+    pub fn private_pages(&self) -> impl Iterator<Item = VirtPageNum> + '_ {
+        self.data_frames
+            .iter()
+            .filter(|(_, frame)| Arc::strong_count(frame) == 1)
+            .map(|(vpn, _)| *vpn)
+    }
+    fn pte_flags(&self) -> PTEFlags {
+        PTEFlags::from_bits(self.map_perm.bits).unwrap()
+    }
+    /// Change the permission of the area and rewrite the flags of its resident pages,
+    /// copy-on-write pages stay read-only until they are written. A private frame
+    /// still shared with another space, like a read-only page at fork, becomes
+    /// copy-on-write as well.
+    pub fn set_permission(&mut self, page_table: &mut PageTable, permission: MapPermission) {
+        self.map_perm = permission;
+        let flags = self.pte_flags();
+        for (vpn, frame) in self.data_frames.iter() {
+            if self.map_type != MapType::Shared && Arc::strong_count(frame) > 1 {
+                self.cow_pages.insert(*vpn);
             }
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap();
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+            if self.cow_pages.contains(vpn) {
+                page_table.set_flags(*vpn, flags - PTEFlags::W);
+            } else {
+                page_table.set_flags(*vpn, flags);
+            }
+        }
+    }
+    /// Map every resident page of this area into `another` as well, sharing the frames.
+    /// Writable pages become read-only copy-on-write pages in both areas,
+    /// except for those of shared memory, which stay shared.
+    /// Return None if there is no memory left for the page tables of `another`.
+    pub fn share_with(
+        &mut self,
+        another: &mut MapArea,
+        page_table: &mut PageTable,
+        another_page_table: &mut PageTable,
+    ) -> Option<()> {
+        let mut flags = self.pte_flags();
+        let writable = flags.contains(PTEFlags::W) && self.map_type != MapType::Shared;
+        if writable {
+            flags.remove(PTEFlags::W);
+        }
+        for (vpn, frame) in self.data_frames.iter() {
+            if writable {
+                page_table.set_flags(*vpn, flags);
+                self.cow_pages.insert(*vpn);
+                another.cow_pages.insert(*vpn);
             }
+            another_page_table.map(*vpn, frame.ppn, flags)?;
+            another.data_frames.insert(*vpn, frame.clone());
+        }
+        // a swapped out page is only read, so the slot can be shared as it is
+        for (vpn, slot) in self.swapped.iter() {
+            another.swapped.insert(*vpn, slot.clone());
+        }
+        Some(())
+    }
+    /// Make the copy-on-write page `vpn` writable again. If its frame is still
+    /// shared with another space, the data is copied into `new_frame` first.
+    pub fn copy_on_write(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        new_frame: Option<FrameTracker>,
+    ) {
+        self.cow_pages.remove(&vpn);
+        let flags = self.pte_flags();
+        let frame = self.data_frames.get_mut(&vpn).unwrap();
+        if let Some(new_frame) = new_frame {
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            page_table.remap(vpn, new_frame.ppn, flags);
+            *frame = Arc::new(new_frame);
+        } else {
+            page_table.set_flags(vpn, flags);
         }
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
     }
     #[allow(unused)]
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn);
+        // a swapped out page is not in the page table
+        if self.swapped.remove(&vpn).is_some() {
+            return;
+        }
+        match self.map_type {
+            MapType::Identical => {}
+            MapType::Framed | MapType::Shared | MapType::Lazy => {
+                // a page which was never mapped, like a lazy page which was never
+                // touched, is not in the page table
+                if self.data_frames.remove(&vpn).is_none() {
+                    return;
+                }
+                self.cow_pages.remove(&vpn);
+            }
         }
         page_table.unmap(vpn);
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+    /// pages which are present in the page table
+    pub fn resident_pages(&self) -> Vec<VirtPageNum> {
+        match self.map_type {
+            MapType::Identical => self.vpn_range.into_iter().collect(),
+            _ => self.data_frames.keys().copied().collect(),
+        }
+    }
+    /// Split an identical area into the largest aligned superpages,
+    /// return the first page and the level of each of them.
+    fn superpages(&self) -> Vec<(VirtPageNum, usize)> {
+        let end = self.vpn_range.get_end().0;
+        let mut vpn = self.vpn_range.get_start().0;
+        let mut v = Vec::new();
+        while vpn < end {
+            let level = (0..PAGE_LEVELS)
+                .rev()
+                .find(|&level| vpn % level_pages(level) == 0 && vpn + level_pages(level) <= end)
+                .unwrap();
+            v.push((VirtPageNum(vpn), level));
+            vpn += level_pages(level);
+        }
+        v
+    }
+    /// Map every page, except for a lazy area whose frames are allocated on page faults.
+    /// An identical area is mapped with superpages where possible.
+    /// Return None if there is no memory left, with the pages mapped so far kept.
+    pub fn map(&mut self, page_table: &mut PageTable) -> Option<()> {
+        match self.map_type {
+            MapType::Lazy => {}
+            MapType::Identical => {
+                for (vpn, level) in self.superpages() {
+                    page_table.map_huge(vpn, PhysPageNum(vpn.0), level, self.pte_flags())?;
+                }
+            }
+            MapType::Framed => {
+                for vpn in self.vpn_range {
+                    self.map_one(page_table, vpn)?;
+                }
+            }
+            MapType::Shared => {
+                let frames = &self.shm.as_ref().unwrap().frames;
+                for (vpn, frame) in self.vpn_range.into_iter().zip(frames.iter()) {
+                    page_table.map(vpn, frame.ppn, self.pte_flags())?;
+                    self.data_frames.insert(vpn, frame.clone());
+                }
+            }
+        }
+        Some(())
+    }
+    /// about how many frames mapping the area takes, for its pages and their page tables
+    fn frames_to_map(&self) -> usize {
+        let pages = self.vpn_range.get_end().0 - self.vpn_range.get_start().0;
+        match self.map_type {
+            MapType::Lazy => 0,
+            MapType::Framed => pages + table_frames(pages),
+            MapType::Identical | MapType::Shared => table_frames(pages),
         }
     }
     #[allow(unused)]
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::Identical {
+            for (vpn, _) in self.superpages() {
+                page_table.unmap(vpn);
+            }
+            return;
+        }
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
@@ -332,42 +1000,53 @@ impl MapArea {
         }
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
+    /// Grow the area to `new_end`, return None and keep its old end
+    /// if there is no memory left.
     #[allow(unused)]
-    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
-        for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
-            self.map_one(page_table, vpn)
-        }
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> Option<()> {
+        let old_end = self.vpn_range.get_end();
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        if self.map_type != MapType::Lazy {
+            for vpn in VPNRange::new(old_end, new_end) {
+                if self.map_one(page_table, vpn).is_none() {
+                    self.shrink_to(page_table, old_end);
+                    return None;
+                }
+            }
+        }
+        Some(())
     }
-    /// data: start-aligned but maybe with shorter length
+    /// data: starts `offset` bytes into the first page, maybe shorter than the area
     /// assume that all frames were cleared before
-    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
+    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8], offset: usize) {
         assert_eq!(self.map_type, MapType::Framed);
         let mut start: usize = 0;
+        let mut page_offset = offset;
         let mut current_vpn = self.vpn_range.get_start();
         let len = data.len();
-        loop {
-            let src = &data[start..len.min(start + PAGE_SIZE)];
+        while start < len {
+            let src = &data[start..len.min(start + PAGE_SIZE - page_offset)];
             let dst = &mut page_table
                 .translate(current_vpn)
                 .unwrap()
                 .ppn()
-                .get_bytes_array()[..src.len()];
+                .get_bytes_array()[page_offset..page_offset + src.len()];
             dst.copy_from_slice(src);
-            start += PAGE_SIZE;
-            if start >= len {
-                break;
-            }
+            start += src.len();
+            page_offset = 0;
             current_vpn.step();
         }
     }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
-/// map type for memory set: identical or framed
+/// map type for memory set: identical, framed, lazily framed on page faults
+/// or the frames of a shared memory segment
 pub enum MapType {
     Identical,
     Framed,
+    Lazy,
+    Shared,
 }
 
 bitflags! {
@@ -401,5 +1080,10 @@ pub fn remap_test() {
         .translate(mid_data.floor())
         .unwrap()
         .executable(),);
+    let mid_memory: VirtAddr = ((ekernel as usize + MEMORY_END) / 2).into();
+    assert_eq!(
+        kernel_space.page_table.translate_va(mid_memory).unwrap().0,
+        mid_memory.0
+    );
     println!("remap_test passed!");
 }
@@ -1,28 +1,43 @@
 //! Memory management implementation
 //!
-//! SV39 page-based virtual-memory architecture for RV64 systems, and
+//! SV39 (or SV48 with the `sv48` feature) page-based virtual-memory
+//! architecture for RV64 systems, and
 //! everything about memory management, like frame allocator, page table,
 //! map area and memory set, is implemented here.
 //!
 //! Every task or process has a memory_set to control its virtual memory.
 
 mod address;
+mod asid;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
+mod swap;
+mod user_ptr;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, FrameTracker};
+pub use frame_allocator::{
+    buddy_allocator_test, frame_alloc, frame_alloc_contiguous, frame_stats, FrameRangeTracker,
+    FrameTracker,
+};
 pub use memory_set::remap_test;
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, PageTableEntry};
-use page_table::{PTEFlags, PageTable};
+pub use memory_set::{
+    frame_alloc_or_swap, kernel_token, register_user_spaces, reserve_kernel_frames, ElfInfo,
+    MapPermission, MemorySet, KERNEL_SPACE,
+};
+use page_table::{level_pages, PTEFlags};
+pub use page_table::{PageTable, PageTableEntry};
+pub use shm::{shm_create, shm_find, shm_remove, shm_segment, ShmSegment};
+pub use user_ptr::{copy_from_user, copy_to_user, translated_str, UserSlice};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
-    KERNEL_SPACE.exclusive_access().activate();
+    memory_set::init_kernel_space();
+    asid::init();
+    swap::init();
 }
@@ -1,9 +1,12 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 
-use super::{frame_alloc, FrameTracker, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::asid::{refresh_asid, Asid};
+use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
+use crate::config::{PAGE_LEVELS, SATP_MODE};
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
+use core::arch::asm;
 
 bitflags! {
     /// page table entry flags
@@ -53,42 +56,59 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    /// a valid entry is a leaf if it is readable, writable or executable,
+    /// otherwise it points to the page table of the next level
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid() && (self.readable() || self.writable() || self.executable())
+    }
+}
+
+/// number of pages covered by a leaf entry which has `level` levels of page table below it,
+/// 0 for a 4 KiB page, 1 for a 2 MiB megapage, 2 for a 1 GiB gigapage
+/// and 3 for a 512 GiB terapage in sv48
+pub fn level_pages(level: usize) -> usize {
+    1 << (9 * level)
 }
 
 /// page table structure
 pub struct PageTable {
     root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
+    asid: Asid,
 }
 
-/// Assume that it won't oom when creating/mapping.
+/// Creating and mapping return `None` when there are no frames left for the page tables.
 impl PageTable {
-    pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        PageTable {
+    pub fn new() -> Option<Self> {
+        let frame = frame_alloc()?;
+        Some(PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
-        }
+            asid: Asid::KERNEL,
+        })
     }
     /// Temporarily used to get arguments from user space.
     pub fn from_token(satp: usize) -> Self {
         Self {
             root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
             frames: Vec::new(),
+            asid: Asid::KERNEL,
         }
     }
-    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    /// find the entry of `vpn` with `level` levels of page table below it
+    fn find_pte_create(&mut self, vpn: VirtPageNum, level: usize) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
+            if i == PAGE_LEVELS - 1 - level {
                 result = Some(pte);
                 break;
             }
+            assert!(!pte.is_leaf(), "vpn {:?} is inside a superpage", vpn);
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
@@ -96,14 +116,16 @@ impl PageTable {
         }
         result
     }
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    /// find the leaf entry covering `vpn` and the number of levels below it,
+    /// or the invalid last level entry if `vpn` is not mapped
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
+        let mut result: Option<(&mut PageTableEntry, usize)> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
+            if i == PAGE_LEVELS - 1 || pte.is_leaf() {
+                result = Some((pte, PAGE_LEVELS - 1 - i));
                 break;
             }
             if !pte.is_valid() {
@@ -114,44 +136,95 @@ impl PageTable {
         result
     }
     #[allow(unused)]
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Option<()> {
+        self.map_huge(vpn, ppn, 0, flags)
+    }
+    /// map a superpage of `level_pages(level)` pages, both `vpn` and `ppn` must be aligned to it
+    pub fn map_huge(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        level: usize,
+        flags: PTEFlags,
+    ) -> Option<()> {
+        let pages = level_pages(level);
+        assert!(
+            vpn.0 % pages == 0 && ppn.0 % pages == 0,
+            "vpn {:?} or ppn {:?} is not aligned to the superpage",
+            vpn,
+            ppn
+        );
+        let pte = self.find_pte_create(vpn, level)?;
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.flush(vpn);
+        Some(())
     }
     #[allow(unused)]
+    /// unmap the page or the whole superpage containing `vpn`
     pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let pte = self.find_pte(vpn).unwrap();
+        let (pte, _) = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
+        self.flush(vpn);
     }
+    /// point the mapped page `vpn` to the frame `ppn`, which needs no new page table
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let (pte, _) = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before remapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.flush(vpn);
+    }
+    /// change the flags of a mapped page, keeping its frame
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let (pte, _) = self.find_pte(vpn).unwrap();
+        assert!(
+            pte.is_valid(),
+            "vpn {:?} is invalid before setting flags",
+            vpn
+        );
+        *pte = PageTableEntry::new(pte.ppn(), flags | PTEFlags::V);
+        self.flush(vpn);
+    }
+    /// clear the accessed bit of a mapped page, return whether it was set
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) -> bool {
+        let (pte, _) = self.find_pte(vpn).unwrap();
+        let flags = pte.flags();
+        *pte = PageTableEntry::new(pte.ppn(), flags - PTEFlags::A);
+        self.flush(vpn);
+        flags.contains(PTEFlags::A)
+    }
+    /// drop the TLB entries of `vpn` in this address space after its pte changed
+    fn flush(&self, vpn: VirtPageNum) {
+        let va: usize = VirtAddr::from(vpn).into();
+        unsafe {
+            asm!("sfence.vma {}, {}", in(reg) va, in(reg) self.asid.id);
+        }
+    }
+    /// the entry of `vpn`, which for a page inside a superpage is that of a 4 KiB page
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_pte(vpn).map(|(pte, level)| {
+            let offset = vpn.0 & (level_pages(level) - 1);
+            PageTableEntry::new(PhysPageNum(pte.ppn().0 + offset), pte.flags())
+        })
+    }
+    pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
+        self.translate(va.floor()).map(|pte| {
+            let aligned_pa: PhysAddr = pte.ppn().into();
+            let offset = va.page_offset();
+            let aligned_pa_usize: usize = aligned_pa.into();
+            (aligned_pa_usize + offset).into()
+        })
+    }
+    pub fn root_ppn(&self) -> PhysPageNum {
+        self.root_ppn
     }
     pub fn token(&self) -> usize {
-        8usize << 60 | self.root_ppn.0
+        SATP_MODE << 60 | self.asid.id << 44 | self.root_ppn.0
     }
-}
-
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    let mut v = Vec::new();
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
-        }
-        start = end_va.into();
+    /// get an ASID for the user space if its old one was taken by a rollover, return the token
+    pub fn user_token(&mut self) -> usize {
+        self.asid = refresh_asid(self.asid);
+        self.token()
     }
-    v
 }
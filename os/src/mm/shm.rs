@@ -0,0 +1,80 @@
+//! Shared memory segments, which are mapped into several [`MemorySet`](super::MemorySet)s
+//!
+//! A segment owns its frames and stays in the table, even when no area attaches
+//! it, until it is removed by `shmctl(IPC_RMID)`. Removing it frees its id and key
+//! at once, and every area attaching it holds the segment, so its frames are freed
+//! when the last of them goes away as well, either by `shmdt`, `munmap` or the exit
+//! of the task.
+
+use super::{frame_alloc_or_swap, FrameTracker};
+use crate::utils::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// the key which always creates a new segment
+pub const IPC_PRIVATE: usize = 0;
+
+/// a set of frames shared between address spaces
+pub struct ShmSegment {
+    pub id: usize,
+    pub key: usize,
+    pub frames: Vec<Arc<FrameTracker>>,
+}
+
+/// all segments, indexed by id
+struct ShmTable {
+    next_id: usize,
+    segments: BTreeMap<usize, Arc<ShmSegment>>,
+}
+
+lazy_static! {
+    static ref SHM_TABLE: UPSafeCell<ShmTable> = unsafe {
+        UPSafeCell::new(ShmTable {
+            next_id: 1,
+            segments: BTreeMap::new(),
+        })
+    };
+}
+
+/// Find the segment with `key`, return its id and number of pages.
+/// `IPC_PRIVATE` never finds one.
+pub fn shm_find(key: usize) -> Option<(usize, usize)> {
+    if key == IPC_PRIVATE {
+        return None;
+    }
+    SHM_TABLE
+        .exclusive_access()
+        .segments
+        .values()
+        .find(|segment| segment.key == key)
+        .map(|segment| (segment.id, segment.frames.len()))
+}
+
+/// Create a segment of `pages` zeroed pages with `key` and return its id,
+/// None if memory runs out.
+pub fn shm_create(key: usize, pages: usize) -> Option<usize> {
+    let mut frames = Vec::new();
+    for _ in 0..pages {
+        frames.push(Arc::new(frame_alloc_or_swap()?));
+    }
+    let mut table = SHM_TABLE.exclusive_access();
+    let id = table.next_id;
+    table.next_id += 1;
+    table
+        .segments
+        .insert(id, Arc::new(ShmSegment { id, key, frames }));
+    Some(id)
+}
+
+/// find the segment with `id` to attach it, if it exists
+pub fn shm_segment(id: usize) -> Option<Arc<ShmSegment>> {
+    SHM_TABLE.exclusive_access().segments.get(&id).cloned()
+}
+
+/// Remove the segment with `id` from the table, return false if there is none.
+/// The areas still attaching it keep it until they are unmapped.
+pub fn shm_remove(id: usize) -> bool {
+    SHM_TABLE.exclusive_access().segments.remove(&id).is_some()
+}
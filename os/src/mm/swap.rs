@@ -0,0 +1,81 @@
+//! Swap area on the block device, holding user pages evicted from memory
+
+use super::PhysPageNum;
+use crate::config::{PAGE_SIZE, SWAP_PAGES};
+use crate::drivers::{BLOCK_DEVICE, BLOCK_SZ};
+use crate::utils::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SZ;
+
+/// allocator of page sized slots in the swap area
+struct SwapAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl SwapAllocator {
+    fn new() -> Self {
+        Self {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(id) = self.recycled.pop() {
+            Some(id)
+        } else if self.current == SWAP_PAGES {
+            None
+        } else {
+            self.current += 1;
+            Some(self.current - 1)
+        }
+    }
+    fn dealloc(&mut self, id: usize) {
+        // validity check
+        if id >= self.current || self.recycled.iter().any(|&i| i == id) {
+            panic!("Swap slot {} has not been allocated!", id);
+        }
+        self.recycled.push(id);
+    }
+}
+
+lazy_static! {
+    static ref SWAP_ALLOCATOR: UPSafeCell<SwapAllocator> =
+        unsafe { UPSafeCell::new(SwapAllocator::new()) };
+}
+
+/// a page in the swap area, which is freed when the slot is dropped
+pub struct SwapSlot {
+    id: usize,
+}
+
+impl SwapSlot {
+    /// write the frame `ppn` into a free slot, None if the swap area is full
+    pub fn write(ppn: PhysPageNum) -> Option<Self> {
+        let id = SWAP_ALLOCATOR.exclusive_access().alloc()?;
+        for (i, block) in ppn.get_bytes_array().chunks(BLOCK_SZ).enumerate() {
+            BLOCK_DEVICE.write_block(id * BLOCKS_PER_PAGE + i, block);
+        }
+        Some(Self { id })
+    }
+    /// read the page back into the frame `ppn`
+    pub fn read(&self, ppn: PhysPageNum) {
+        for (i, block) in ppn.get_bytes_array().chunks_mut(BLOCK_SZ).enumerate() {
+            BLOCK_DEVICE.read_block(self.id * BLOCKS_PER_PAGE + i, block);
+        }
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_ALLOCATOR.exclusive_access().dealloc(self.id);
+    }
+}
+
+/// probe the block device at boot, so that its queues are not allocated
+/// at the moment memory has run out
+pub fn init() {
+    lazy_static::initialize(&BLOCK_DEVICE);
+}
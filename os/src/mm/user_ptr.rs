@@ -0,0 +1,168 @@
+//! Safe access to the user space from the kernel.
+//!
+//! Every access is checked against the `U`, `R` and `W` bits of the page
+//! table of the given [`MemorySet`], so a bad pointer from a syscall turns into
+//! `None`, which the syscall reports as `-EFAULT`, instead of a kernel panic.
+//! Lazy and swapped out pages are faulted in and copy-on-write pages are copied
+//! before the kernel writes to them, just like on an access from the user.
+
+use super::{MapPermission, MemorySet, PTEFlags, PageTableEntry, PhysPageNum, VirtAddr};
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+/// whether the user may access the page of `pte`, for a write if `write` is set
+fn user_accessible(pte: &PageTableEntry, write: bool) -> bool {
+    pte.is_valid()
+        && pte.flags().contains(PTEFlags::U)
+        && if write {
+            pte.writable()
+        } else {
+            pte.readable()
+        }
+}
+
+/// translate `va` of `memory_set` into the frame holding it, faulting the page in
+/// first if needed, return None if the user may not access it
+fn translate_user_va(memory_set: &mut MemorySet, va: usize, write: bool) -> Option<PhysPageNum> {
+    if va >= USER_SPACE_END {
+        return None;
+    }
+    let vpn = VirtAddr::from(va).floor();
+    if !memory_set
+        .translate(vpn)
+        .map_or(false, |pte| user_accessible(&pte, write))
+    {
+        let access = if write {
+            MapPermission::W
+        } else {
+            MapPermission::R
+        };
+        if !memory_set.handle_page_fault(va.into(), access) {
+            return None;
+        }
+    }
+    memory_set
+        .translate(vpn)
+        .filter(|pte| user_accessible(pte, write))
+        .map(|pte| pte.ppn())
+}
+
+/// `len` bytes at `ptr` in the user space `memory_set`
+pub struct UserSlice<'a> {
+    memory_set: &'a mut MemorySet,
+    ptr: usize,
+    len: usize,
+}
+
+impl<'a> UserSlice<'a> {
+    pub fn new(memory_set: &'a mut MemorySet, ptr: *const u8, len: usize) -> Self {
+        Self {
+            memory_set,
+            ptr: ptr as usize,
+            len,
+        }
+    }
+    /// Call `f` with the piece of the slice in every page in order, for a write if
+    /// `write` is set. Return None when reaching a byte which can not be accessed.
+    /// A page is used right after it is translated, since translating the next one
+    /// may swap it out.
+    fn for_each_page(&mut self, write: bool, mut f: impl FnMut(&mut [u8])) -> Option<()> {
+        let mut start = self.ptr;
+        let end = start.checked_add(self.len)?;
+        while start < end {
+            let ppn = translate_user_va(self.memory_set, start, write)?;
+            let offset = start % PAGE_SIZE;
+            let piece = (PAGE_SIZE - offset).min(end - start);
+            f(&mut ppn.get_bytes_array()[offset..offset + piece]);
+            start += piece;
+        }
+        Some(())
+    }
+    /// whether the whole slice can be accessed, for a write if `write` is set
+    pub fn is_accessible(&mut self, write: bool) -> bool {
+        self.for_each_page(write, |_| {}).is_some()
+    }
+    /// Call `f` with the piece of the slice in every page in order, without copying
+    /// the slice. Return None when reaching a byte which can not be read.
+    pub fn read_pages(&mut self, mut f: impl FnMut(&[u8])) -> Option<()> {
+        self.for_each_page(false, |piece| f(piece))
+    }
+    /// copy the slice into a `Vec`, only for small slices since it is allocated at once
+    pub fn read(&mut self) -> Option<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.len);
+        self.for_each_page(false, |piece| data.extend_from_slice(piece))?;
+        Some(data)
+    }
+    /// copy `data` into the slice, None if their lengths differ.
+    /// the pages before a bad one are written when None is returned
+    pub fn write(&mut self, data: &[u8]) -> Option<()> {
+        if data.len() != self.len {
+            return None;
+        }
+        let mut start = 0;
+        self.for_each_page(true, |piece| {
+            let end = start + piece.len();
+            piece.copy_from_slice(&data[start..end]);
+            start = end;
+        })
+    }
+}
+
+/// a pointer to a `T` in the user space `memory_set`, which may cross pages
+pub struct UserPtr<'a, T> {
+    slice: UserSlice<'a>,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<'a, T> UserPtr<'a, T> {
+    pub fn new(memory_set: &'a mut MemorySet, ptr: *const T) -> Self {
+        Self {
+            slice: UserSlice::new(memory_set, ptr as *const u8, core::mem::size_of::<T>()),
+            _marker: PhantomData,
+        }
+    }
+    /// copy `value` into the user space
+    pub fn write(&mut self, value: &T) -> Option<()> {
+        let data = unsafe {
+            core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
+        };
+        self.slice.write(data)
+    }
+}
+
+impl<T: Copy> UserPtr<'_, T> {
+    /// copy the `T` out of the user space
+    pub fn read(&mut self) -> Option<T> {
+        let data = self.slice.read()?;
+        Some(unsafe { (data.as_ptr() as *const T).read_unaligned() })
+    }
+}
+
+/// copy the `T` at `src` in user space, None if it can not be read
+pub fn copy_from_user<T: Copy>(memory_set: &mut MemorySet, src: *const T) -> Option<T> {
+    UserPtr::new(memory_set, src).read()
+}
+
+/// copy `src` into the `T` at `dst` in user space, None if it can not be written
+pub fn copy_to_user<T>(memory_set: &mut MemorySet, dst: *mut T, src: &T) -> Option<()> {
+    UserPtr::new(memory_set, dst).write(src)
+}
+
+/// Copy the bytes of a nul-terminated string in user space, at most `max` of them,
+/// None if some byte of it can not be read. The bytes are kept as they are, they
+/// need not be UTF-8. At most `max + 1` bytes are read, a longer string comes back
+/// cut there so that the caller sees it is too long.
+pub fn translated_str(memory_set: &mut MemorySet, ptr: *const u8, max: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut va = ptr as usize;
+    for _ in 0..=max {
+        let byte = translate_user_va(memory_set, va, false)?.get_bytes_array()[va % PAGE_SIZE];
+        if byte == 0 {
+            break;
+        }
+        bytes.push(byte);
+        va = va.checked_add(1)?;
+    }
+    Some(bytes)
+}
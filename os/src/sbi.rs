@@ -6,6 +6,12 @@ pub fn console_putchar(c: usize) {
     sbi_rt::legacy::console_putchar(c);
 }
 
+/// use sbi call to getchar from console (qemu uart handler)
+pub fn console_getchar() -> usize {
+    #[allow(deprecated)]
+    sbi_rt::legacy::console_getchar()
+}
+
 /// use sbi call to set timer
 pub fn set_timer(timer: usize) {
     sbi_rt::set_timer(timer as _);
@@ -0,0 +1,36 @@
+//! Error numbers of syscalls, the same as those of Linux
+
+/// an error of a syscall, the user sees its value negated
+#[repr(isize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SysError {
+    /// no such file or directory
+    ENOENT = 2,
+    /// no such process
+    ESRCH = 3,
+    /// argument list too long
+    E2BIG = 7,
+    /// bad file descriptor
+    EBADF = 9,
+    /// no child processes
+    ECHILD = 10,
+    /// try again
+    EAGAIN = 11,
+    /// out of memory
+    ENOMEM = 12,
+    /// bad address
+    EFAULT = 14,
+    /// already exists
+    EEXIST = 17,
+    /// invalid argument
+    EINVAL = 22,
+    /// not a terminal
+    ENOTTY = 25,
+    /// file name too long
+    ENAMETOOLONG = 36,
+    /// function not implemented
+    ENOSYS = 38,
+}
+
+/// the result of a syscall, a non-negative value or an error
+pub type SysResult = Result<usize, SysError>;
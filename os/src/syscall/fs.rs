@@ -1,22 +1,70 @@
 //! File and filesystem-related syscalls
 
-use crate::mm::translated_byte_buffer;
-use crate::task::current_user_token;
+use super::{SysError, SysResult};
+use crate::mm::UserSlice;
+use crate::sbi::{console_getchar, console_putchar};
+use crate::task::{suspend_current_and_run_next, with_current_memory_set};
 
+const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
+const FD_STDERR: usize = 2;
 
-/// write buf of length `len`  to a file with `fd`
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
+/// write buf of length `len`  to a file with `fd`, return EBADF if it is not stdout
+/// or stderr, which both go to the console
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
     match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
+        FD_STDOUT | FD_STDERR => {
+            with_current_memory_set(|memory_set| {
+                let mut slice = UserSlice::new(memory_set, buf, len);
+                // check the whole buffer first, so that nothing is printed on EFAULT
+                if !slice.is_accessible(false) {
+                    return None;
+                }
+                // print page by page, a large buffer is never copied into the kernel heap
+                slice.read_pages(|piece| {
+                    for byte in piece {
+                        console_putchar(*byte as usize);
+                    }
+                })
+            })
+            .ok_or(SysError::EFAULT)?;
+            Ok(len)
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+        _ => Err(SysError::EBADF),
+    }
+}
+
+/// read at most one byte from a file with `fd` into buf, return the number of bytes read
+/// or EBADF if it is not stdin
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> SysResult {
+    match fd {
+        FD_STDIN => {
+            if len == 0 {
+                return Ok(0);
+            }
+            // check before waiting, so that no input is lost
+            if !with_current_memory_set(|memory_set| {
+                UserSlice::new(memory_set, buf, 1).is_accessible(true)
+            }) {
+                return Err(SysError::EFAULT);
+            }
+            let mut c: usize;
+            loop {
+                c = console_getchar();
+                // the legacy sbi call returns 0 or -1 when there is no input yet
+                if c == 0 || c == usize::MAX {
+                    suspend_current_and_run_next();
+                    continue;
+                } else {
+                    break;
+                }
+            }
+            with_current_memory_set(|memory_set| {
+                UserSlice::new(memory_set, buf, 1).write(&[c as u8])
+            })
+            .ok_or(SysError::EFAULT)?;
+            Ok(1)
         }
+        _ => Err(SysError::EBADF),
     }
 }
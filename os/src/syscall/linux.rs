@@ -0,0 +1,273 @@
+//! Syscalls of the Linux personality
+//!
+//! Only tasks running Linux programs see them, and they take the place of the
+//! native syscalls with the same ids, like `brk` of `sbrk` or `wait4` of
+//! `waitpid`. The other syscalls of the native table take the arguments Linux
+//! passes and are used as they are: `read`, `write`, `exit`, `nanosleep`,
+//! `clock_gettime`, `sched_yield`, `gettimeofday`, `getpid`, `munmap`,
+//! `mprotect` and the `shm*` calls, `clone` only as a plain fork and `execve`
+//! without the environment. Any other id returns ENOSYS.
+
+use super::fs::sys_write;
+use super::process::{prot_to_permission, reap_child, sys_exit, user_range};
+use super::{SysError, SysResult};
+use crate::config::{DEFAULT_PRIORITY, PAGE_SIZE, USER_SPACE_END};
+use crate::mm::{copy_from_user, copy_to_user};
+use crate::task::{
+    block_current_and_run_next, change_program_brk, current_task, find_task, mmap,
+    with_current_memory_set,
+};
+
+/// get the window size of a terminal, a request of `sys_ioctl`
+const TIOCGWINSZ: usize = 0x5413;
+/// most buffers `sys_writev` takes at once
+const IOV_MAX: usize = 1024;
+/// flags of `sys_linux_mmap`, the memory is private to the task and not backed by a file,
+/// at exactly the given address
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+/// `which` of `sys_setpriority` for a single process
+const PRIO_PROCESS: usize = 0;
+/// options of `sys_wait4`, return at once if no child has exited, and report
+/// stopped or continued children, which never happens
+const WNOHANG: usize = 1;
+const WUNTRACED: usize = 2;
+const WCONTINUED: usize = 8;
+
+/// the window size of a terminal
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct WinSize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+/// a buffer of `sys_writev`
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct IoVec {
+    base: *const u8,
+    len: usize,
+}
+
+/// resource usage of `sys_wait4`, 2 `timeval`s and 14 counters
+#[repr(C)]
+struct RUsage([usize; 18]);
+
+/// names of the system, every one a nul-terminated string of at most 65 bytes
+#[repr(C)]
+struct UtsName {
+    sysname: [u8; 65],
+    nodename: [u8; 65],
+    release: [u8; 65],
+    version: [u8; 65],
+    machine: [u8; 65],
+    domainname: [u8; 65],
+}
+
+fn uts_field(s: &str) -> [u8; 65] {
+    let mut field = [0; 65];
+    field[..s.len()].copy_from_slice(s.as_bytes());
+    field
+}
+
+/// control the device of `fd`. the console is the only device, so stdin, stdout and
+/// stderr report an 80x24 terminal, every other request returns ENOTTY
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> SysResult {
+    if fd > 2 {
+        return Err(SysError::EBADF);
+    }
+    if request != TIOCGWINSZ {
+        return Err(SysError::ENOTTY);
+    }
+    let size = WinSize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    with_current_memory_set(|memory_set| copy_to_user(memory_set, arg as *mut WinSize, &size))
+        .ok_or(SysError::EFAULT)?;
+    Ok(0)
+}
+
+/// write the `iovcnt` buffers described at `iov` in order, return the bytes written.
+/// a buffer which fails stops the writing, and its error is only returned if
+/// nothing was written before
+pub fn sys_writev(fd: usize, iov: *const u8, iovcnt: usize) -> SysResult {
+    if iovcnt > IOV_MAX {
+        return Err(SysError::EINVAL);
+    }
+    let iov = iov as *const IoVec;
+    let mut written = 0;
+    for i in 0..iovcnt {
+        let result =
+            with_current_memory_set(|memory_set| copy_from_user(memory_set, iov.wrapping_add(i)))
+                .ok_or(SysError::EFAULT)
+                .and_then(|buf| sys_write(fd, buf.base, buf.len));
+        match result {
+            Ok(len) => written += len,
+            Err(err) if written == 0 => return Err(err),
+            Err(_) => break,
+        }
+    }
+    Ok(written)
+}
+
+/// exit all threads of the task, which is only the task itself
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    sys_exit(exit_code)
+}
+
+/// the address is cleared when a thread exits, which only matters with threads,
+/// return the tid, which is the pid
+pub fn sys_set_tid_address(_tidptr: usize) -> SysResult {
+    Ok(current_task().getpid())
+}
+
+/// get the names of the system
+pub fn sys_uname(buf: usize) -> SysResult {
+    let uts = UtsName {
+        sysname: uts_field("Linux"),
+        nodename: uts_field("zos"),
+        release: uts_field("5.10.0"),
+        version: uts_field("#1"),
+        machine: uts_field("riscv64"),
+        domainname: uts_field(""),
+    };
+    with_current_memory_set(|memory_set| copy_to_user(memory_set, buf as *mut UtsName, &uts))
+        .ok_or(SysError::EFAULT)?;
+    Ok(0)
+}
+
+/// move the program break to `addr` unless it is 0, return the break after that,
+/// which stays where it was if it can not be moved
+pub fn sys_brk(addr: usize) -> SysResult {
+    let brk = current_task().inner_exclusive_access().program_brk;
+    if addr == 0 {
+        return Ok(brk);
+    }
+    let moved = (addr as isize)
+        .checked_sub(brk as isize)
+        .and_then(|size| i32::try_from(size).ok())
+        .and_then(change_program_brk);
+    Ok(if moved.is_some() { addr } else { brk })
+}
+
+/// Map `len` bytes of zeroed memory private to the task, return its address. With
+/// `MAP_FIXED` it is at `addr`, which must be page aligned. Otherwise `addr` is a hint
+/// used if the range is free, or the memory goes where there is room. There are no
+/// files, so the memory must be `MAP_ANONYMOUS` with `fd` -1, as portable programs
+/// pass, and `offset` is only checked to be page aligned as Linux does. Unlike
+/// Linux, a fixed mapping never replaces an old one but returns EEXIST.
+pub fn sys_linux_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> SysResult {
+    if flags & MAP_ANONYMOUS == 0 || fd as isize != -1 {
+        return Err(SysError::EBADF);
+    }
+    if offset % PAGE_SIZE != 0 {
+        return Err(SysError::EINVAL);
+    }
+    if flags & (MAP_SHARED | MAP_PRIVATE) != MAP_PRIVATE {
+        return Err(SysError::EINVAL);
+    }
+    let permission = prot_to_permission(prot)?;
+    if flags & MAP_FIXED != 0 {
+        let (start, end) = user_range(addr, len)?;
+        if !mmap(start, end, permission) {
+            return Err(SysError::EEXIST);
+        }
+        return Ok(addr);
+    }
+    if len == 0 || len > USER_SPACE_END {
+        return Err(SysError::EINVAL);
+    }
+    if addr != 0 {
+        if let Ok((start, end)) = user_range(addr, len) {
+            if mmap(start, end, permission) {
+                return Ok(addr);
+            }
+        }
+    }
+    let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    with_current_memory_set(|memory_set| memory_set.mmap_anywhere(pages, permission))
+        .map(usize::from)
+        .ok_or(SysError::ENOMEM)
+}
+
+/// Set the nice value of the task `who`, or the current one if it is 0. A nice value
+/// lower by one is a priority higher by one, and nice 0 is the default priority.
+pub fn sys_setpriority(which: usize, who: usize, nice: isize) -> SysResult {
+    if which != PRIO_PROCESS {
+        return Err(SysError::EINVAL);
+    }
+    let task = if who == 0 {
+        current_task()
+    } else {
+        find_task(who).ok_or(SysError::ESRCH)?
+    };
+    let nice = nice.clamp(-20, 19);
+    task.inner_exclusive_access().priority = (DEFAULT_PRIORITY as isize - nice).max(2) as usize;
+    Ok(0)
+}
+
+/// Wait for the child `pid` to exit, or any child if it is -1 or 0 since there are
+/// no process groups, return its pid, or 0 with `WNOHANG` if it is still running.
+/// The task is blocked while it waits and woken up when a child exits.
+/// The status is stored at `wstatus` in the encoding of Linux, resource usage is not
+/// tracked and stored at `rusage` as all zero.
+pub fn sys_wait4(pid: isize, wstatus: *mut i32, options: usize, rusage: usize) -> SysResult {
+    if options & !(WNOHANG | WUNTRACED | WCONTINUED) != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let pid = match pid {
+        0 => -1,
+        pid if pid < -1 => return Err(SysError::ECHILD),
+        pid => pid,
+    };
+    let (found_pid, exit_code) = loop {
+        match reap_child(pid) {
+            Err(SysError::EAGAIN) if options & WNOHANG != 0 => return Ok(0),
+            Err(SysError::EAGAIN) => {
+                current_task().inner_exclusive_access().waiting_for_child = true;
+                block_current_and_run_next();
+            }
+            result => break result?,
+        }
+    };
+    with_current_memory_set(|memory_set| {
+        if !wstatus.is_null() {
+            copy_to_user(memory_set, wstatus, &((exit_code & 0xff) << 8))?;
+        }
+        if rusage != 0 {
+            copy_to_user(memory_set, rusage as *mut RUsage, &RUsage([0; 18]))?;
+        }
+        Some(())
+    })
+    .ok_or(SysError::EFAULT)?;
+    Ok(found_pid)
+}
+
+/// get the id of the thread, which is the pid as every task has a single thread
+pub fn sys_gettid() -> SysResult {
+    Ok(current_task().getpid())
+}
+
+/// There are no signals, so every mask is accepted and the old one is empty.
+pub fn sys_rt_sigprocmask(_how: usize, _set: usize, oldset: usize) -> SysResult {
+    if oldset != 0 {
+        with_current_memory_set(|memory_set| copy_to_user(memory_set, oldset as *mut u64, &0))
+            .ok_or(SysError::EFAULT)?;
+    }
+    Ok(0)
+}
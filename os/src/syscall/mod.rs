@@ -10,26 +10,149 @@
 //! `sys_` then the name of the syscall. You can find functions like this in
 //! submodules, and you should also implement syscalls this way.
 
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_WRITEV: usize = 66;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_SET_TID_ADDRESS: usize = 96;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_RT_SIGPROCMASK: usize = 135;
+const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_UNAME: usize = 160;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
 const SYSCALL_SBRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_FORK: usize = 220;
+const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_TASK_INFO: usize = 410;
 
+mod errno;
 mod fs;
+mod linux;
 mod process;
 
+use crate::config::MAX_SYSCALL_NUM;
+use crate::task::{current_task, Personality};
+use crate::timer::{TimeSpec, TimeVal};
+pub use errno::{SysError, SysResult};
 use fs::*;
+use linux::*;
 use process::*;
 
-/// handle syscall exception with `syscall_id` and other arguments
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
-    match syscall_id {
-        SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
-        SYSCALL_EXIT => sys_exit(args[0] as i32),
-        SYSCALL_YIELD => sys_yield(),
-        SYSCALL_GET_TIME => sys_get_time(),
-        SYSCALL_SBRK => sys_sbrk(args[0] as i32),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+/// a syscall, which picks its arguments out of a0-a5
+type Handler = fn([usize; 6]) -> SysResult;
+
+/// every supported syscall, adding one only takes an entry here
+const SYSCALL_TABLE: &[(usize, Handler)] = &[
+    (SYSCALL_READ, |args| {
+        sys_read(args[0], args[1] as *const u8, args[2])
+    }),
+    (SYSCALL_WRITE, |args| {
+        sys_write(args[0], args[1] as *const u8, args[2])
+    }),
+    (SYSCALL_EXIT, |args| sys_exit(args[0] as i32)),
+    (SYSCALL_NANOSLEEP, |args| {
+        sys_nanosleep(args[0] as *const TimeSpec, args[1] as *mut TimeSpec)
+    }),
+    (SYSCALL_CLOCK_GETTIME, |args| {
+        sys_clock_gettime(args[0], args[1] as *mut TimeSpec)
+    }),
+    (SYSCALL_YIELD, |_| sys_yield()),
+    (SYSCALL_SET_PRIORITY, |args| {
+        sys_set_priority(args[0] as isize)
+    }),
+    (SYSCALL_GET_TIME, |args| {
+        sys_get_time(args[0] as *mut TimeVal, args[1])
+    }),
+    (SYSCALL_GETPID, |_| sys_getpid()),
+    (SYSCALL_SHMGET, |args| sys_shmget(args[0], args[1])),
+    (SYSCALL_SHMCTL, |args| sys_shmctl(args[0], args[1], args[2])),
+    (SYSCALL_SHMAT, |args| sys_shmat(args[0], args[1], args[2])),
+    (SYSCALL_SHMDT, |args| sys_shmdt(args[0])),
+    (SYSCALL_SBRK, |args| sys_sbrk(args[0] as i32)),
+    (SYSCALL_MUNMAP, |args| sys_munmap(args[0], args[1])),
+    (SYSCALL_FORK, |_| sys_fork()),
+    (SYSCALL_EXEC, |args| {
+        sys_exec(args[0] as *const u8, args[1] as *const usize)
+    }),
+    (SYSCALL_MMAP, |args| sys_mmap(args[0], args[1], args[2])),
+    (SYSCALL_MPROTECT, |args| {
+        sys_mprotect(args[0], args[1], args[2])
+    }),
+    (SYSCALL_WAITPID, |args| {
+        sys_waitpid(args[0] as isize, args[1] as *mut i32)
+    }),
+    (SYSCALL_TASK_INFO, |args| {
+        sys_task_info(args[0] as isize, args[1] as *mut TaskInfo)
+    }),
+];
+
+/// syscalls of the Linux personality, looked up before the native ones
+const LINUX_SYSCALL_TABLE: &[(usize, Handler)] = &[
+    (SYSCALL_IOCTL, |args| sys_ioctl(args[0], args[1], args[2])),
+    (SYSCALL_WRITEV, |args| {
+        sys_writev(args[0], args[1] as *const u8, args[2])
+    }),
+    (SYSCALL_EXIT_GROUP, |args| sys_exit_group(args[0] as i32)),
+    (SYSCALL_SET_TID_ADDRESS, |args| sys_set_tid_address(args[0])),
+    (SYSCALL_UNAME, |args| sys_uname(args[0])),
+    (SYSCALL_SBRK, |args| sys_brk(args[0])),
+    (SYSCALL_MMAP, |args| {
+        sys_linux_mmap(args[0], args[1], args[2], args[3], args[4], args[5])
+    }),
+    (SYSCALL_SET_PRIORITY, |args| {
+        sys_setpriority(args[0], args[1], args[2] as isize)
+    }),
+    (SYSCALL_WAITPID, |args| {
+        sys_wait4(args[0] as isize, args[1] as *mut i32, args[2], args[3])
+    }),
+    (SYSCALL_GETTID, |_| sys_gettid()),
+    (SYSCALL_RT_SIGPROCMASK, |args| {
+        sys_rt_sigprocmask(args[0], args[1], args[2])
+    }),
+];
+
+/// handle syscall exception with `syscall_id` and the arguments in a0-a5,
+/// an error is returned to the user as its negated errno
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    let personality = {
+        let task = current_task();
+        let mut inner = task.inner_exclusive_access();
+        if syscall_id < MAX_SYSCALL_NUM {
+            inner.syscall_times[syscall_id] += 1;
+        }
+        inner.personality
+    };
+    let personality_table = match personality {
+        Personality::Native => &[],
+        Personality::Linux => LINUX_SYSCALL_TABLE,
+    };
+    let result = match personality_table
+        .iter()
+        .chain(SYSCALL_TABLE)
+        .find(|(id, _)| *id == syscall_id)
+    {
+        Some((_, handler)) => handler(args),
+        None => {
+            println!("[kernel] Unsupported syscall_id: {}", syscall_id);
+            Err(SysError::ENOSYS)
+        }
+    };
+    match result {
+        Ok(ret) => ret as isize,
+        Err(err) => -(err as isize),
     }
 }
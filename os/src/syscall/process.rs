@@ -1,31 +1,367 @@
 //! Process management syscalls
 
-use crate::task::{change_program_brk, exit_current_and_run_next, suspend_current_and_run_next};
-use crate::timer::get_time_ms;
+use super::{SysError, SysResult};
+use crate::config::{MAX_SYSCALL_NUM, PAGE_SIZE, USER_SPACE_END, USER_STACK_SIZE};
+use crate::loader::{get_app_data_by_name, is_linux_app};
+use crate::mm::{
+    copy_from_user, copy_to_user, shm_create, shm_find, shm_remove, shm_segment, translated_str,
+    MapPermission, VirtAddr,
+};
+use crate::task::{
+    add_task, block_current_and_run_next, change_program_brk, current_task,
+    exit_current_and_run_next, find_task, mmap, mprotect, munmap, remove_task, shm_detach,
+    suspend_current_and_run_next, with_current_memory_set, Personality, TaskStatus,
+};
+use crate::timer::{
+    add_timer, get_real_time_ns, get_time, get_time_ns, ticks_to_ms, TimeSpec, TimeVal,
+    CLOCK_MONOTONIC, CLOCK_REALTIME,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::mem::size_of;
+
+/// most bytes the arguments of `sys_exec` take on the user stack, half of it
+const ARG_MAX: usize = USER_STACK_SIZE / 2;
+/// most arguments of `sys_exec`, as many as `_start` of the user library takes
+const MAX_ARGS: usize = 32;
+/// longest path `sys_exec` accepts, without its `\0`
+const PATH_MAX: usize = 4096;
+
+/// information about a task, as returned by `sys_task_info`
+#[repr(C)]
+pub struct TaskInfo {
+    /// status of the task
+    status: TaskStatus,
+    /// number of calls of every syscall, indexed by syscall id
+    syscall_times: [u32; MAX_SYSCALL_NUM],
+    /// time when the task was first scheduled in ms, 0 if it never ran
+    first_scheduled: usize,
+    /// total time the task has been running in ms
+    run_time: usize,
+}
 
 /// task exits and submit an exit code
 pub fn sys_exit(exit_code: i32) -> ! {
     println!("[kernel] Application exited with code {}", exit_code);
-    exit_current_and_run_next();
+    exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
 /// current task gives up resources for other tasks
-pub fn sys_yield() -> isize {
+pub fn sys_yield() -> SysResult {
     suspend_current_and_run_next();
-    0
+    Ok(0)
+}
+
+/// block current task for the duration in `req`, `rem` is set to zero if not null.
+/// return EINVAL if `req` is invalid.
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> SysResult {
+    let ticks = with_current_memory_set(|memory_set| copy_from_user(memory_set, req))
+        .ok_or(SysError::EFAULT)?
+        .to_ticks()
+        .ok_or(SysError::EINVAL)?;
+    add_timer(get_time().saturating_add(ticks), current_task());
+    block_current_and_run_next();
+    // we are never woken up early, so there is no time left
+    let zero = TimeSpec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if !rem.is_null() {
+        with_current_memory_set(|memory_set| copy_to_user(memory_set, rem, &zero))
+            .ok_or(SysError::EFAULT)?;
+    }
+    Ok(0)
+}
+
+/// get wall clock time into `ts` if not null, `tz` is obsolete and ignored
+pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> SysResult {
+    let tv = TimeVal::from_nanos(get_real_time_ns());
+    if !ts.is_null() {
+        with_current_memory_set(|memory_set| copy_to_user(memory_set, ts, &tv))
+            .ok_or(SysError::EFAULT)?;
+    }
+    Ok(0)
+}
+
+/// get the time of `clock_id` into `tp`, return EINVAL if the clock is not supported
+pub fn sys_clock_gettime(clock_id: usize, tp: *mut TimeSpec) -> SysResult {
+    let nanos = match clock_id {
+        CLOCK_REALTIME => get_real_time_ns(),
+        CLOCK_MONOTONIC => get_time_ns(),
+        _ => return Err(SysError::EINVAL),
+    };
+    with_current_memory_set(|memory_set| {
+        copy_to_user(memory_set, tp, &TimeSpec::from_nanos(nanos))
+    })
+    .ok_or(SysError::EFAULT)?;
+    Ok(0)
+}
+
+/// change data segment size, return the old end of it
+pub fn sys_sbrk(size: i32) -> SysResult {
+    change_program_brk(size).ok_or(SysError::ENOMEM)
+}
+
+/// check that `[start, start + len)` is a non-empty, page aligned range of user space
+pub fn user_range(start: usize, len: usize) -> Result<(VirtAddr, VirtAddr), SysError> {
+    if start % PAGE_SIZE != 0 || len == 0 {
+        return Err(SysError::EINVAL);
+    }
+    match start.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => Ok((start.into(), end.into())),
+        _ => Err(SysError::EINVAL),
+    }
+}
+
+/// convert `prot` of mmap and mprotect into the permission of a user area,
+/// EINVAL if it has unknown bits or none at all
+pub fn prot_to_permission(prot: usize) -> Result<MapPermission, SysError> {
+    if prot & !0x7 != 0 || prot & 0x7 == 0 {
+        return Err(SysError::EINVAL);
+    }
+    let mut permission = MapPermission::from_bits((prot << 1) as u8).unwrap() | MapPermission::U;
+    // a writable page which is not readable is a reserved pte encoding
+    if permission.contains(MapPermission::W) {
+        permission |= MapPermission::R;
+    }
+    Ok(permission)
+}
+
+/// map `len` bytes of zeroed memory at `start`, `prot` is a combination of
+/// read (1), write (2) and exec (4). return EINVAL if the arguments are invalid
+/// or EEXIST if the range is in use.
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> SysResult {
+    let (start, end) = user_range(start, len)?;
+    if !mmap(start, end, prot_to_permission(prot)?) {
+        return Err(SysError::EEXIST);
+    }
+    Ok(0)
+}
+
+/// unmap `len` bytes at `start`, return EINVAL if some page in the range is not mapped
+/// or in the heap
+pub fn sys_munmap(start: usize, len: usize) -> SysResult {
+    let (start, end) = user_range(start, len)?;
+    if !munmap(start, end) {
+        return Err(SysError::EINVAL);
+    }
+    Ok(0)
+}
+
+/// change the permission of `len` bytes at `start` to `prot`, as in `sys_mmap`.
+/// return ENOMEM if some page in the range is not mapped or in the heap
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> SysResult {
+    let (start, end) = user_range(start, len)?;
+    let permission = prot_to_permission(prot)?;
+    if !mprotect(start, end, permission) {
+        return Err(SysError::ENOMEM);
+    }
+    if permission.contains(MapPermission::X) {
+        // the pages may have been written as data, fetch them as code from now on
+        unsafe { asm!("fence.i") };
+    }
+    Ok(0)
 }
 
-/// get current time
-pub fn sys_get_time() -> isize {
-    get_time_ms() as isize
+/// attach the segment read-only, a flag of `sys_shmat`
+const SHM_RDONLY: usize = 0o10000;
+/// remove a segment, a command of `sys_shmctl`
+const IPC_RMID: usize = 0;
+
+/// get the id of the shared memory segment with `key`, creating one of at least `size`
+/// bytes if there is none or `key` is `IPC_PRIVATE`. return EINVAL if `size` is 0 or larger
+/// than the existing segment, and ENOMEM if there is no memory for a new one.
+pub fn sys_shmget(key: usize, size: usize) -> SysResult {
+    if size == 0 || size > USER_SPACE_END {
+        return Err(SysError::EINVAL);
+    }
+    let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+    if let Some((id, existing)) = shm_find(key) {
+        return if pages <= existing {
+            Ok(id)
+        } else {
+            Err(SysError::EINVAL)
+        };
+    }
+    shm_create(key, pages).ok_or(SysError::ENOMEM)
+}
+
+/// attach the segment `shmid` at the page aligned `addr`, or where there is room if it is 0.
+/// return the address it is attached at, or EINVAL if the segment does not exist or the
+/// range is in use.
+pub fn sys_shmat(shmid: usize, addr: usize, shmflg: usize) -> SysResult {
+    if addr % PAGE_SIZE != 0 || shmflg & !SHM_RDONLY != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let shm = shm_segment(shmid).ok_or(SysError::EINVAL)?;
+    let mut permission = MapPermission::R | MapPermission::U;
+    if shmflg & SHM_RDONLY == 0 {
+        permission |= MapPermission::W;
+    }
+    let start = (addr != 0).then_some(VirtAddr::from(addr));
+    crate::task::shm_attach(shm, start, permission)
+        .map(usize::from)
+        .ok_or(SysError::EINVAL)
+}
+
+/// detach the segment attached at `addr`, return EINVAL if there is none
+pub fn sys_shmdt(addr: usize) -> SysResult {
+    if addr % PAGE_SIZE != 0 || !shm_detach(addr.into()) {
+        return Err(SysError::EINVAL);
+    }
+    Ok(0)
+}
+
+/// control the segment `shmid`. `IPC_RMID` is the only command, which removes the
+/// segment so that its id and key are no longer found, while the areas attaching it
+/// keep it until they are detached, and `buf` is ignored. return EINVAL for another
+/// command or if the segment does not exist.
+pub fn sys_shmctl(shmid: usize, cmd: usize, _buf: usize) -> SysResult {
+    if cmd != IPC_RMID || !shm_remove(shmid) {
+        return Err(SysError::EINVAL);
+    }
+    Ok(0)
+}
+
+/// get the pid of current task
+pub fn sys_getpid() -> SysResult {
+    Ok(current_task().getpid())
 }
 
-/// change data segment size
-pub fn sys_sbrk(size: i32) -> isize {
-    if let Some(old_brk) = change_program_brk(size) {
-        old_brk as isize
+/// create a child task, return 0 in the child and the child's pid in the parent,
+/// or ENOMEM if there is no memory for it
+pub fn sys_fork() -> SysResult {
+    let current_task = current_task();
+    let new_task = current_task.fork().ok_or(SysError::ENOMEM)?;
+    let new_pid = new_task.getpid();
+    // we do not have to move to next instruction since we have done it before
+    // for child process, fork returns 0
+    let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
+    trap_cx.x[10] = 0;
+    add_task(new_task);
+    Ok(new_pid)
+}
+
+/// replace the current task with the app named by `path`, passing it the nul-terminated
+/// strings in the null-terminated array `argv`, or no arguments if `argv` is null.
+/// return ENOENT if there is no such app, ENAMETOOLONG if `path` is too long and E2BIG
+/// if there are more than `MAX_ARGS` arguments or they take more than `ARG_MAX` bytes,
+/// which always fit into the new user stack, and ENOMEM if there is no memory left.
+pub fn sys_exec(path: *const u8, argv: *const usize) -> SysResult {
+    let task = current_task();
+    let mut inner = task.inner_exclusive_access();
+    let memory_set = &mut inner.memory_set;
+    let path = translated_str(memory_set, path, PATH_MAX).ok_or(SysError::EFAULT)?;
+    if path.len() > PATH_MAX {
+        return Err(SysError::ENAMETOOLONG);
+    }
+    let mut args = Vec::new();
+    let mut size = 0;
+    while !argv.is_null() {
+        let arg =
+            copy_from_user(memory_set, argv.wrapping_add(args.len())).ok_or(SysError::EFAULT)?;
+        if arg == 0 {
+            break;
+        }
+        if args.len() == MAX_ARGS {
+            return Err(SysError::E2BIG);
+        }
+        // the string, its `\0` and its pointer in argv must fit into what is left
+        let max = (ARG_MAX - size).saturating_sub(1 + size_of::<usize>());
+        let arg = translated_str(memory_set, arg as *const u8, max).ok_or(SysError::EFAULT)?;
+        size += arg.len() + 1 + size_of::<usize>();
+        if size > ARG_MAX {
+            return Err(SysError::E2BIG);
+        }
+        args.push(arg);
+    }
+    drop(inner);
+    // app names are UTF-8, so other bytes name no app
+    let path = core::str::from_utf8(&path).map_err(|_| SysError::ENOENT)?;
+    let data = get_app_data_by_name(path).ok_or(SysError::ENOENT)?;
+    let personality = if is_linux_app(path) {
+        Personality::Linux
     } else {
-        -1
+        Personality::Native
+    };
+    if !task.exec(data, &args, personality) {
+        return Err(SysError::ENOMEM);
+    }
+    // the return value goes to a0, where the new program expects argc
+    Ok(args.len())
+}
+
+/// Reap the child with `pid`, or any child if `pid` is -1, return its pid and exit code.
+/// return ECHILD if there is no such child, or EAGAIN if it is still running.
+pub fn reap_child(pid: isize) -> Result<(usize, i32), SysError> {
+    let task = current_task();
+    // find a child process
+    let mut inner = task.inner_exclusive_access();
+    if !inner
+        .children
+        .iter()
+        .any(|p| pid == -1 || pid as usize == p.getpid())
+    {
+        return Err(SysError::ECHILD);
     }
+    let pair = inner.children.iter().enumerate().find(|(_, p)| {
+        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
+    });
+    let Some((idx, _)) = pair else {
+        return Err(SysError::EAGAIN);
+    };
+    let child = inner.children.remove(idx);
+    drop(inner);
+    remove_task(&child);
+    // confirm that child will be deallocated after being removed from children list
+    assert_eq!(Arc::strong_count(&child), 1);
+    let exit_code = child.inner_exclusive_access().exit_code;
+    Ok((child.getpid(), exit_code))
+}
+
+/// If there is not a child process whose pid is same as given, return ECHILD.
+/// Else if there is a child process but it is still running, return EAGAIN.
+/// The exit code is stored into `exit_code_ptr` if it is not null,
+/// EFAULT is returned if that fails, but the child is reaped anyway.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> SysResult {
+    let (found_pid, exit_code) = reap_child(pid)?;
+    if !exit_code_ptr.is_null() {
+        with_current_memory_set(|memory_set| copy_to_user(memory_set, exit_code_ptr, &exit_code))
+            .ok_or(SysError::EFAULT)?;
+    }
+    Ok(found_pid)
+}
+
+/// set the priority of current task, it must be at least 2
+pub fn sys_set_priority(prio: isize) -> SysResult {
+    if prio < 2 {
+        return Err(SysError::EINVAL);
+    }
+    current_task().inner_exclusive_access().priority = prio as usize;
+    Ok(prio as usize)
+}
+
+/// get information about the task with `pid`, or current task if `pid` is negative.
+/// return ESRCH if there is no such task.
+pub fn sys_task_info(pid: isize, ti: *mut TaskInfo) -> SysResult {
+    let task = if pid < 0 {
+        current_task()
+    } else {
+        find_task(pid as usize).ok_or(SysError::ESRCH)?
+    };
+    let inner = task.inner_exclusive_access();
+    let mut syscall_times = [0u32; MAX_SYSCALL_NUM];
+    syscall_times.copy_from_slice(&inner.syscall_times);
+    let task_info = TaskInfo {
+        status: inner.task_status,
+        syscall_times,
+        first_scheduled: inner.first_scheduled.map_or(0, ticks_to_ms),
+        run_time: ticks_to_ms(inner.total_run_time()),
+    };
+    drop(inner);
+    with_current_memory_set(|memory_set| copy_to_user(memory_set, ti, &task_info))
+        .ok_or(SysError::EFAULT)?;
+    Ok(0)
 }
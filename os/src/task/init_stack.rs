@@ -0,0 +1,125 @@
+//! The initial user stack of a program
+//!
+//! The arguments are nul-terminated strings at the top of the stack, below
+//! them is the null-terminated array `argv` of pointers to them. A native
+//! program gets `argc` and `argv` in `a0` and `a1`. A Linux program finds
+//! `argc`, `argv`, `envp` and the auxiliary vector at `sp`, in the layout of
+//! the System V ABI which the `_start` of musl expects.
+
+use crate::config::PAGE_SIZE;
+use crate::mm::{ElfInfo, MemorySet, UserSlice};
+use crate::timer::get_time;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of_val;
+
+/// end of the auxiliary vector
+const AT_NULL: usize = 0;
+/// address of the program headers
+const AT_PHDR: usize = 3;
+/// size of a program header
+const AT_PHENT: usize = 4;
+/// number of program headers
+const AT_PHNUM: usize = 5;
+/// page size
+const AT_PAGESZ: usize = 6;
+/// entry point of the program
+const AT_ENTRY: usize = 9;
+/// real and effective user and group ids, with them musl knows it is not setuid
+const AT_UID: usize = 11;
+const AT_EUID: usize = 12;
+const AT_GID: usize = 13;
+const AT_EGID: usize = 14;
+/// whether the program runs with raised privileges
+const AT_SECURE: usize = 23;
+/// address of 16 random bytes
+const AT_RANDOM: usize = 25;
+
+/// pushes data downwards from the top of a user stack
+struct InitStack<'a> {
+    memory_set: &'a mut MemorySet,
+    sp: usize,
+}
+
+impl<'a> InitStack<'a> {
+    fn new(memory_set: &'a mut MemorySet, top: usize) -> Self {
+        Self {
+            memory_set,
+            sp: top,
+        }
+    }
+    /// push `data`, return its address, or None if it does not fit into the stack
+    fn push_bytes(&mut self, data: &[u8]) -> Option<usize> {
+        self.sp = self.sp.checked_sub(data.len())?;
+        UserSlice::new(self.memory_set, self.sp as *const u8, data.len()).write(data)?;
+        Some(self.sp)
+    }
+    /// push `s` with a trailing `\0`, return its address
+    fn push_str(&mut self, s: &[u8]) -> Option<usize> {
+        self.push_bytes(&[0])?;
+        self.push_bytes(s)
+    }
+    /// push every one of `args`, return their addresses
+    fn push_args(&mut self, args: &[Vec<u8>]) -> Option<Vec<usize>> {
+        args.iter().map(|arg| self.push_str(arg)).collect()
+    }
+    /// push `words` at an address aligned to 16 bytes as the ABI requires for `sp`
+    fn push_words(&mut self, words: &[usize]) -> Option<usize> {
+        let len = size_of_val(words);
+        self.sp = self.sp.checked_sub(len)? & !0xf;
+        let data = unsafe { core::slice::from_raw_parts(words.as_ptr() as *const u8, len) };
+        UserSlice::new(self.memory_set, self.sp as *const u8, len).write(data)?;
+        Some(self.sp)
+    }
+}
+
+/// lay out `args` on the user stack ending at `top`, return the initial `sp`,
+/// which is also where `argv` is, or None if they do not fit into the stack
+pub fn native_init_stack(
+    memory_set: &mut MemorySet,
+    top: usize,
+    args: &[Vec<u8>],
+) -> Option<usize> {
+    let mut stack = InitStack::new(memory_set, top);
+    let mut argv = stack.push_args(args)?;
+    argv.push(0);
+    stack.push_words(&argv)
+}
+
+/// lay out `args` and the auxiliary vector of the program in `elf` on the user stack
+/// ending at `top`, return the initial `sp`, or None if they do not fit into the stack
+pub fn linux_init_stack(
+    memory_set: &mut MemorySet,
+    top: usize,
+    args: &[Vec<u8>],
+    elf: &ElfInfo,
+) -> Option<usize> {
+    let mut stack = InitStack::new(memory_set, top);
+    // not really random, but different in every program
+    let seed = (get_time() as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    let random =
+        stack.push_bytes(&[seed.to_le_bytes(), seed.rotate_left(32).to_le_bytes()].concat())?;
+    let argv = stack.push_args(args)?;
+    let mut words = vec![args.len()];
+    words.extend(argv);
+    // ends argv, then an empty envp
+    words.extend([0, 0]);
+    let auxv = [
+        (AT_PHDR, elf.phdr),
+        (AT_PHENT, elf.phent),
+        (AT_PHNUM, elf.phnum),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, elf.entry),
+        (AT_UID, 0),
+        (AT_EUID, 0),
+        (AT_GID, 0),
+        (AT_EGID, 0),
+        (AT_SECURE, 0),
+        (AT_RANDOM, random),
+        (AT_NULL, 0),
+    ];
+    for (key, value) in auxv {
+        words.extend([key, value]);
+    }
+    stack.push_words(&words)
+}
@@ -10,20 +10,29 @@
 //! might not be what you expect.
 
 mod context;
+mod init_stack;
+mod pid;
+mod scheduler;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
-use crate::loader::{get_app_data, get_num_app};
+use crate::loader::get_app_data_by_name;
+use crate::mm::{register_user_spaces, MapPermission, MemorySet, ShmSegment, VirtAddr};
 use crate::sbi::shutdown;
 use crate::trap::TrapContext;
 use crate::utils::UPSafeCell;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
 use alloc::vec::Vec;
 use lazy_static::*;
+use riscv::asm::wfi;
+use riscv::register::sstatus;
+use scheduler::{EnqueueReason, Scheduler, SchedulerImpl};
 use switch::__switch;
-use task::{TaskControlBlock, TaskStatus};
 
 pub use context::TaskContext;
+pub(crate) use task::{Personality, TaskControlBlock, TaskStatus};
 
 /// The task manager, where all the tasks are managed.
 ///
@@ -35,36 +44,44 @@ pub use context::TaskContext;
 /// borrowing checks to runtime. You can see examples on how to use `inner` in
 /// existing functions on `TaskManager`.
 pub struct TaskManager {
-    /// total number of tasks
-    num_app: usize,
     /// use inner value to get mutable access
     inner: UPSafeCell<TaskManagerInner>,
 }
 
 /// The task manager inner in 'UPSafeCell'
 struct TaskManagerInner {
-    /// task list
-    tasks: Vec<TaskControlBlock>,
-    /// id of current `Running` task
-    current_task: usize,
+    /// task list, zombies stay here until their parent waits for them
+    tasks: Vec<Arc<TaskControlBlock>>,
+    /// current `Running` task
+    current_task: Option<Arc<TaskControlBlock>>,
+    /// scheduling policy holding all `Ready` tasks
+    scheduler: SchedulerImpl,
+    /// context of the idle loop, which runs on the boot stack
+    idle_task_cx: TaskContext,
+}
+
+lazy_static! {
+    /// the first user task, it adopts all orphans and never exits
+    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(
+        TaskControlBlock::new(get_app_data_by_name("initproc").unwrap())
+            .expect("no memory for initproc")
+    );
 }
 
 lazy_static! {
     /// a `TaskManager` global instance through lazy_static!
     pub static ref TASK_MANAGER: TaskManager = {
         println!("init TASK_MANAGER");
-        let num_app = get_num_app();
-        println!("num_app = {}", num_app);
-        let mut tasks: Vec<TaskControlBlock> = Vec::new();
-        for i in 0..num_app {
-            tasks.push(TaskControlBlock::new(get_app_data(i), i));
-        }
+        let tasks: Vec<Arc<TaskControlBlock>> = vec![INITPROC.clone()];
+        let mut scheduler = SchedulerImpl::new();
+        scheduler.enqueue(INITPROC.clone(), EnqueueReason::New);
         TaskManager {
-            num_app,
             inner: unsafe {
                 UPSafeCell::new(TaskManagerInner {
                     tasks,
-                    current_task: 0,
+                    current_task: None,
+                    scheduler,
+                    idle_task_cx: TaskContext::zero_init(),
                 })
             },
         }
@@ -72,78 +89,252 @@ lazy_static! {
 }
 
 impl TaskManager {
-    /// Run the first task in task list.
+    /// Run the idle loop, starting with the first task `INITPROC`.
     ///
-    /// Generally, the first task in task list is an idle task (we call it zero process later).
-    /// But in ch4, we load apps statically, so the first task is a real app.
-    fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let next_task = &mut inner.tasks[0];
-        next_task.task_status = TaskStatus::Running;
-        let next_task_cx_ptr = &next_task.task_cx as *const TaskContext;
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        // before this, we should drop local variables that must be dropped manually
-        unsafe {
-            __switch(&mut _unused as *mut _, next_task_cx_ptr);
+    /// The idle loop switches to the next `Ready` task whenever there is one.
+    /// A task switches back here when it cannot find another task to run, then
+    /// we wait for interrupts if some tasks are blocked, or shut down once all
+    /// tasks have exited.
+    fn run_idle(&self) -> ! {
+        loop {
+            if let Some(next) = self.find_next_task() {
+                let mut next_task_inner = next.inner_exclusive_access();
+                next_task_inner.start_running();
+                let next_task_cx_ptr = &next_task_inner.task_cx as *const TaskContext;
+                drop(next_task_inner);
+                let mut inner = self.inner.exclusive_access();
+                let idle_task_cx_ptr = &mut inner.idle_task_cx as *mut TaskContext;
+                inner.current_task = Some(next);
+                drop(inner);
+                // before this, we should drop local variables that must be dropped manually
+                unsafe {
+                    __switch(idle_task_cx_ptr, next_task_cx_ptr);
+                }
+            } else if self.all_tasks_exited() {
+                println!("All applications completed!");
+                shutdown(false);
+            } else {
+                // the timer interrupt handler will wake up sleeping tasks
+                unsafe {
+                    sstatus::set_sie();
+                    wfi();
+                    sstatus::clear_sie();
+                }
+            }
         }
-        panic!("unreachable in run_first_task!");
     }
 
-    /// Change the status of current `Running` task into `Ready`.
-    fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Ready;
+    /// Whether every task in the task list is a `Zombie`.
+    fn all_tasks_exited(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        inner
+            .tasks
+            .iter()
+            .all(|task| task.inner_exclusive_access().is_zombie())
     }
 
-    /// Change the status of current `Running` task into `Exited`.
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Exited;
+    /// Change the status of current `Running` task into `Ready`
+    /// and give it back to the scheduler for `reason`.
+    fn mark_current_suspended(&self, reason: EnqueueReason) {
+        let task = self.current_task();
+        task.inner_exclusive_access().task_status = TaskStatus::Ready;
+        self.inner
+            .exclusive_access()
+            .scheduler
+            .enqueue(task, reason);
     }
 
-    /// Find next task to run and return task id.
+    /// Change the status of current `Running` task into `Blocked`,
+    /// it is not given back to the scheduler until someone wakes it up.
+    fn mark_current_blocked(&self) {
+        let task = self.current_task();
+        task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+    }
+
+    /// Change the status of a `Blocked` task into `Ready`
+    /// and give it back to the scheduler.
+    fn wakeup_task(&self, task: Arc<TaskControlBlock>) {
+        let mut task_inner = task.inner_exclusive_access();
+        assert!(task_inner.task_status == TaskStatus::Blocked);
+        task_inner.task_status = TaskStatus::Ready;
+        drop(task_inner);
+        self.inner
+            .exclusive_access()
+            .scheduler
+            .enqueue(task, EnqueueReason::Wakeup);
+    }
+
+    /// Change the status of current `Running` task into `Zombie`, record its
+    /// exit code and release its user memory.
     ///
-    /// In this case, we only return the first `Ready` task in task list.
-    fn find_next_task(&self) -> Option<usize> {
+    /// The task stays in the task list until its parent waits for it, which is
+    /// woken up if it is blocked waiting for a child, and so is initproc if it
+    /// adopts a zombie.
+    fn mark_current_exited(&self, exit_code: i32) {
+        let task = self.current_task();
+        if Arc::ptr_eq(&task, &INITPROC) {
+            println!("[kernel] initproc exited with code {}", exit_code);
+            shutdown(exit_code != 0);
+        }
+        let mut inner = task.inner_exclusive_access();
+        inner.task_status = TaskStatus::Zombie;
+        inner.exit_code = exit_code;
+        // move all children to initproc, it will wait for them
+        let mut adopted_zombie = false;
+        {
+            let mut initproc_inner = INITPROC.inner_exclusive_access();
+            for child in inner.children.iter() {
+                let mut child_inner = child.inner_exclusive_access();
+                child_inner.parent = Some(Arc::downgrade(&INITPROC));
+                adopted_zombie |= child_inner.task_status == TaskStatus::Zombie;
+                initproc_inner.children.push(child.clone());
+            }
+        }
+        inner.children.clear();
+        let parent = inner.parent.as_ref().and_then(Weak::upgrade);
+        for task in parent
+            .into_iter()
+            .chain(adopted_zombie.then(|| INITPROC.clone()))
+        {
+            let mut task_inner = task.inner_exclusive_access();
+            if task_inner.waiting_for_child {
+                task_inner.waiting_for_child = false;
+                drop(task_inner);
+                self.wakeup_task(task);
+            }
+        }
+        // deallocate user space early, the page table is still needed until we switch away
+        inner.memory_set.recycle_data_pages();
+    }
+
+    /// Find next task to run, which is decided by the scheduler.
+    fn find_next_task(&self) -> Option<Arc<TaskControlBlock>> {
+        self.inner.exclusive_access().scheduler.pick_next()
+    }
+
+    /// Get the current 'Running' task, there is none while idling.
+    fn current_task(&self) -> Arc<TaskControlBlock> {
+        self.inner.exclusive_access().current_task.clone().unwrap()
+    }
+
+    /// Tell the scheduler that a timer tick has passed,
+    /// return whether the current task should be preempted.
+    fn on_tick(&self) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        let current = inner.current_task.clone().unwrap();
+        inner.scheduler.on_tick(&current)
+    }
+
+    /// Find a task in the task list by its pid.
+    fn find_task(&self, pid: usize) -> Option<Arc<TaskControlBlock>> {
         let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        (current + 1..current + self.num_app + 1)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
+        inner
+            .tasks
+            .iter()
+            .find(|task| task.getpid() == pid)
+            .cloned()
+    }
+
+    /// Add a new `Ready` task to the task list and the scheduler.
+    fn add_task(&self, task: Arc<TaskControlBlock>) {
+        let mut inner = self.inner.exclusive_access();
+        inner.tasks.push(task.clone());
+        inner.scheduler.enqueue(task, EnqueueReason::New);
+    }
+
+    /// Remove a reaped task from the task list.
+    fn remove_task(&self, task: &Arc<TaskControlBlock>) {
+        let mut inner = self.inner.exclusive_access();
+        if let Some(idx) = inner.tasks.iter().position(|t| Arc::ptr_eq(t, task)) {
+            inner.tasks.remove(idx);
+        }
     }
 
     /// Get the current 'Running' task's token.
     fn get_current_token(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_user_token()
+        self.current_task()
+            .inner_exclusive_access()
+            .get_user_token()
     }
 
     /// Get the current 'Running' task's trap contexts.
     fn get_current_trap_cx(&self) -> &'static mut TrapContext {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_trap_cx()
+        self.current_task().inner_exclusive_access().get_trap_cx()
     }
 
     /// Change the current 'Running' task's program break
     pub fn change_current_program_brk(&self, size: i32) -> Option<usize> {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].change_program_brk(size)
+        self.current_task().change_program_brk(size)
+    }
+
+    /// Map anonymous memory into the current 'Running' task's address space
+    pub fn mmap_current(&self, start: VirtAddr, end: VirtAddr, permission: MapPermission) -> bool {
+        let task = self.current_task();
+        let mut inner = task.inner_exclusive_access();
+        inner.memory_set.mmap(start, end, permission)
+    }
+
+    /// Unmap memory from the current 'Running' task's address space
+    pub fn munmap_current(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        let task = self.current_task();
+        let mut inner = task.inner_exclusive_access();
+        inner.memory_set.munmap(start, end)
+    }
+
+    /// Change the permission of memory in the current 'Running' task's address space
+    pub fn mprotect_current(
+        &self,
+        start: VirtAddr,
+        end: VirtAddr,
+        permission: MapPermission,
+    ) -> bool {
+        let task = self.current_task();
+        let mut inner = task.inner_exclusive_access();
+        inner.memory_set.mprotect(start, end, permission)
+    }
+
+    /// Attach a shared memory segment to the current 'Running' task's address space
+    pub fn shm_attach_current(
+        &self,
+        shm: Arc<ShmSegment>,
+        start: Option<VirtAddr>,
+        permission: MapPermission,
+    ) -> Option<VirtAddr> {
+        let task = self.current_task();
+        let mut inner = task.inner_exclusive_access();
+        inner.memory_set.shm_attach(shm, start, permission)
+    }
+
+    /// Detach a shared memory segment from the current 'Running' task's address space
+    pub fn shm_detach_current(&self, start: VirtAddr) -> bool {
+        let task = self.current_task();
+        let mut inner = task.inner_exclusive_access();
+        inner.memory_set.shm_detach(start)
+    }
+
+    /// Handle a page fault of the current 'Running' task at `va`
+    pub fn handle_current_page_fault(&self, va: VirtAddr, access: MapPermission) -> bool {
+        let task = self.current_task();
+        let mut inner = task.inner_exclusive_access();
+        inner.memory_set.handle_page_fault(va, access)
     }
 
     /// Switch current `Running` task to the task we have found,
-    /// or there is no `Ready` task and we can exit with all applications completed
+    /// or there is no `Ready` task and we go back to the idle loop
     fn run_next_task(&self) {
         if let Some(next) = self.find_next_task() {
+            let mut next_task_inner = next.inner_exclusive_access();
+            next_task_inner.start_running();
+            let next_task_cx_ptr = &next_task_inner.task_cx as *const TaskContext;
+            drop(next_task_inner);
             let mut inner = self.inner.exclusive_access();
-            let current = inner.current_task;
-            inner.tasks[next].task_status = TaskStatus::Running;
-            inner.current_task = next;
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
+            // the previous task is still owned by the task list
+            let current = inner.current_task.replace(next).unwrap();
+            let mut current_task_inner = current.inner_exclusive_access();
+            current_task_inner.stop_running();
+            let current_task_cx_ptr = &mut current_task_inner.task_cx as *mut TaskContext;
+            drop(current_task_inner);
+            drop(current);
             drop(inner);
             // before this, we should drop local variables that must be dropped manually
             unsafe {
@@ -151,50 +342,138 @@ impl TaskManager {
             }
             // go back to user mode
         } else {
-            println!("All applications completed!");
-            shutdown(false);
+            let mut inner = self.inner.exclusive_access();
+            let current = inner.current_task.take().unwrap();
+            let idle_task_cx_ptr = &inner.idle_task_cx as *const TaskContext;
+            let mut current_task_inner = current.inner_exclusive_access();
+            current_task_inner.stop_running();
+            let current_task_cx_ptr = &mut current_task_inner.task_cx as *mut TaskContext;
+            drop(current_task_inner);
+            drop(current);
+            drop(inner);
+            unsafe {
+                __switch(current_task_cx_ptr, idle_task_cx_ptr);
+            }
         }
     }
 }
 
-/// Run the first task in task list.
+/// Run the first task in task list, the boot stack becomes the idle loop.
 pub fn run_first_task() {
-    TASK_MANAGER.run_first_task();
+    lazy_static::initialize(&TASK_MANAGER);
+    // pages of the tasks can be swapped out from now on
+    register_user_spaces(visit_user_spaces);
+    TASK_MANAGER.run_idle();
+}
+
+/// Call `f` with the user space of every task which is not in use until it returns
+/// true, return whether it did.
+fn visit_user_spaces(f: &mut dyn FnMut(&mut MemorySet) -> bool) -> bool {
+    let tasks = match TASK_MANAGER.inner.try_exclusive_access() {
+        Some(inner) => inner.tasks.clone(),
+        None => return false,
+    };
+    tasks.iter().any(|task| {
+        task.try_inner_exclusive_access()
+            .map_or(false, |mut inner| f(&mut inner.memory_set))
+    })
 }
 
 /// Switch current `Running` task to the task we have found,
-/// or there is no `Ready` task and we can exit with all applications completed
+/// or there is no `Ready` task and we go back to the idle loop
 fn run_next_task() {
     TASK_MANAGER.run_next_task();
 }
 
 /// Change the status of current `Running` task into `Ready`.
-fn mark_current_suspended() {
-    TASK_MANAGER.mark_current_suspended();
+fn mark_current_suspended(reason: EnqueueReason) {
+    TASK_MANAGER.mark_current_suspended(reason);
 }
 
-/// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
+/// Change the status of current `Running` task into `Zombie`.
+fn mark_current_exited(exit_code: i32) {
+    TASK_MANAGER.mark_current_exited(exit_code);
 }
 
 /// Suspend the current 'Running' task and run the next task in task list.
 pub fn suspend_current_and_run_next() {
-    mark_current_suspended();
+    mark_current_suspended(EnqueueReason::Yield);
     run_next_task();
 }
 
+/// Preempt the current 'Running' task whose time slice is used up
+/// and run the next task in task list.
+pub fn preempt_current_and_run_next() {
+    mark_current_suspended(EnqueueReason::Timeout);
+    run_next_task();
+}
+
+/// Block the current 'Running' task and run the next task in task list.
+pub fn block_current_and_run_next() {
+    TASK_MANAGER.mark_current_blocked();
+    run_next_task();
+}
+
+/// Wake up a `Blocked` task, it will be scheduled later.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.wakeup_task(task);
+}
+
 /// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
+pub fn exit_current_and_run_next(exit_code: i32) {
+    mark_current_exited(exit_code);
     run_next_task();
 }
 
+/// Notify the scheduler of a timer tick,
+/// return whether the current task should be preempted.
+pub fn on_tick() -> bool {
+    TASK_MANAGER.on_tick()
+}
+
+/// Get the current 'Running' task.
+pub fn current_task() -> Arc<TaskControlBlock> {
+    TASK_MANAGER.current_task()
+}
+
+/// Find a task which has not been reaped yet by its pid.
+pub fn find_task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.find_task(pid)
+}
+
+/// Add a new task to the task list, it will be scheduled later.
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.add_task(task);
+}
+
+/// Remove a task that has been reaped by its parent from the task list.
+pub fn remove_task(task: &Arc<TaskControlBlock>) {
+    TASK_MANAGER.remove_task(task);
+}
+
+/// Run `f` with the user space of the current 'Running' task.
+pub fn with_current_memory_set<T>(f: impl FnOnce(&mut MemorySet) -> T) -> T {
+    f(&mut TASK_MANAGER
+        .current_task()
+        .inner_exclusive_access()
+        .memory_set)
+}
+
 /// Get the current 'Running' task's token.
 pub fn current_user_token() -> usize {
     TASK_MANAGER.get_current_token()
 }
 
+/// Get the current 'Running' task's token to return to user space with,
+/// allocating an ASID for it if it has none of the current generation.
+pub fn current_user_satp() -> usize {
+    TASK_MANAGER
+        .current_task()
+        .inner_exclusive_access()
+        .memory_set
+        .user_token()
+}
+
 /// Get the current 'Running' task's trap contexts.
 pub fn current_trap_cx() -> &'static mut TrapContext {
     TASK_MANAGER.get_current_trap_cx()
@@ -204,3 +483,39 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
 pub fn change_program_brk(size: i32) -> Option<usize> {
     TASK_MANAGER.change_current_program_brk(size)
 }
+
+/// Map `[start, end)` into the current 'Running' task, false if it overlaps an area
+pub fn mmap(start: VirtAddr, end: VirtAddr, permission: MapPermission) -> bool {
+    TASK_MANAGER.mmap_current(start, end, permission)
+}
+
+/// Unmap `[start, end)` from the current 'Running' task, false if not all mapped
+pub fn munmap(start: VirtAddr, end: VirtAddr) -> bool {
+    TASK_MANAGER.munmap_current(start, end)
+}
+
+/// Change the permission of `[start, end)` of the current 'Running' task, false if not all mapped
+pub fn mprotect(start: VirtAddr, end: VirtAddr, permission: MapPermission) -> bool {
+    TASK_MANAGER.mprotect_current(start, end, permission)
+}
+
+/// Attach a shared memory segment to the current 'Running' task at `start`,
+/// or where there is room if it is None. Return where it was attached.
+pub fn shm_attach(
+    shm: Arc<ShmSegment>,
+    start: Option<VirtAddr>,
+    permission: MapPermission,
+) -> Option<VirtAddr> {
+    TASK_MANAGER.shm_attach_current(shm, start, permission)
+}
+
+/// Detach the shared memory segment attached at `start` from the current 'Running' task
+pub fn shm_detach(start: VirtAddr) -> bool {
+    TASK_MANAGER.shm_detach_current(start)
+}
+
+/// Map a lazy page of the current 'Running' task on its first `access`,
+/// false if the fault is a real one
+pub fn handle_page_fault(va: VirtAddr, access: MapPermission) -> bool {
+    TASK_MANAGER.handle_current_page_fault(va, access)
+}
@@ -0,0 +1,100 @@
+//! Implementation of [`MlfqScheduler`]
+//!
+//! Tasks live in one of `MLFQ_LEVELS` round robin queues, and a task in a
+//! higher queue always runs before tasks in lower ones. A task which runs for
+//! its whole time slice is demoted, while a task which gives up the cpu before
+//! that (e.g. `sys_yield`) is promoted, so interactive tasks stay on top of
+//! cpu-bound ones. Every `BOOST_INTERVAL` ticks all tasks are moved back to the
+//! top queue so that cpu-bound tasks never starve.
+
+use super::{EnqueueReason, Scheduler, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// number of queues, level 0 has the highest priority
+pub const MLFQ_LEVELS: usize = 3;
+/// timer ticks between two priority resets
+pub const BOOST_INTERVAL: usize = 100;
+
+/// length of the time slice at `level`, in timer ticks
+fn time_slice(level: usize) -> usize {
+    1 << level
+}
+
+/// Multi-level feedback queue.
+pub struct MlfqScheduler {
+    queues: [VecDeque<Arc<TaskControlBlock>>; MLFQ_LEVELS],
+    /// ticks since the last priority reset
+    ticks: usize,
+}
+
+impl MlfqScheduler {
+    /// move every task back to the top queue
+    fn boost(&mut self, current: &Arc<TaskControlBlock>) {
+        for level in 1..MLFQ_LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                let mut inner = task.inner_exclusive_access();
+                inner.mlfq_level = 0;
+                inner.mlfq_ticks = 0;
+                drop(inner);
+                self.queues[0].push_back(task);
+            }
+        }
+        let mut inner = current.inner_exclusive_access();
+        inner.mlfq_level = 0;
+        inner.mlfq_ticks = 0;
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            ticks: 0,
+        }
+    }
+    fn enqueue(&mut self, task: Arc<TaskControlBlock>, reason: EnqueueReason) {
+        let mut inner = task.inner_exclusive_access();
+        // a preempted task is already demoted in `on_tick`, new and woken up
+        // tasks stay where they are
+        if reason == EnqueueReason::Yield && inner.mlfq_level > 0 {
+            // the task gave up the cpu before its time slice ran out
+            inner.mlfq_level -= 1;
+            inner.mlfq_ticks = 0;
+        }
+        let level = inner.mlfq_level;
+        drop(inner);
+        self.queues[level].push_back(task);
+    }
+    fn dequeue(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        for queue in self.queues.iter_mut() {
+            if let Some(idx) = queue.iter().position(|t| Arc::ptr_eq(t, task)) {
+                queue.remove(idx);
+                return true;
+            }
+        }
+        false
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        self.ticks += 1;
+        if self.ticks >= BOOST_INTERVAL {
+            self.ticks = 0;
+            self.boost(current);
+            return true;
+        }
+        let mut inner = current.inner_exclusive_access();
+        inner.mlfq_ticks += 1;
+        if inner.mlfq_ticks < time_slice(inner.mlfq_level) {
+            return false;
+        }
+        // the whole time slice is used up, demote the task
+        inner.mlfq_ticks = 0;
+        if inner.mlfq_level + 1 < MLFQ_LEVELS {
+            inner.mlfq_level += 1;
+        }
+        true
+    }
+}
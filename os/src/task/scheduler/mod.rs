@@ -0,0 +1,51 @@
+//! Scheduling policies
+//!
+//! A [`Scheduler`] owns the set of `Ready` tasks and decides which one runs
+//! next. [`TaskManager`](super::TaskManager) only talks to the policy through
+//! this trait, so switching policies is a matter of changing
+//! [`SchedulerImpl`].
+
+mod mlfq;
+mod round_robin;
+mod stride;
+
+use super::TaskControlBlock;
+use alloc::sync::Arc;
+
+#[allow(unused)]
+pub use mlfq::MlfqScheduler;
+#[allow(unused)]
+pub use round_robin::RoundRobinScheduler;
+#[allow(unused)]
+pub use stride::StrideScheduler;
+
+/// Why a task becomes `Ready`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EnqueueReason {
+    /// a newly created task
+    New,
+    /// the task gave up the cpu by itself
+    Yield,
+    /// the task was preempted by the timer
+    Timeout,
+    /// a blocked task was woken up
+    Wakeup,
+}
+
+/// A scheduling policy over `Ready` tasks.
+pub trait Scheduler {
+    /// Create an empty scheduler.
+    fn new() -> Self;
+    /// Add a task which has just become `Ready` for `reason`.
+    fn enqueue(&mut self, task: Arc<TaskControlBlock>, reason: EnqueueReason);
+    /// Remove a task from the ready set, return whether it was there.
+    fn dequeue(&mut self, task: &Arc<TaskControlBlock>) -> bool;
+    /// Take the next task to run out of the ready set.
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// Called on every timer interrupt with the `Running` task,
+    /// return whether it should be preempted.
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool;
+}
+
+/// The scheduling policy used by the kernel.
+pub type SchedulerImpl = MlfqScheduler;
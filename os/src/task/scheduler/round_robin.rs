@@ -0,0 +1,35 @@
+//! Implementation of [`RoundRobinScheduler`]
+
+use super::{EnqueueReason, Scheduler, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// Run `Ready` tasks in FIFO order, preempting on every timer tick.
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn enqueue(&mut self, task: Arc<TaskControlBlock>, _reason: EnqueueReason) {
+        self.ready_queue.push_back(task);
+    }
+    fn dequeue(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        if let Some(idx) = self.ready_queue.iter().position(|t| Arc::ptr_eq(t, task)) {
+            self.ready_queue.remove(idx);
+            true
+        } else {
+            false
+        }
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn on_tick(&mut self, _current: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+}
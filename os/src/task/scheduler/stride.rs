@@ -0,0 +1,63 @@
+//! Implementation of [`StrideScheduler`]
+//!
+//! Every task has a `stride` which grows by `BIG_STRIDE / priority` each time
+//! it is scheduled, and the `Ready` task with the smallest stride runs next.
+//! A task with priority `p` therefore gets CPU time proportional to `p`.
+
+use super::{EnqueueReason, Scheduler, TaskControlBlock};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Strides are compared with wrapping arithmetic. As priorities are at least 2,
+/// two live strides never differ by more than `BIG_STRIDE / 2`, so the signed
+/// difference of two strides is still correct after they overflow.
+pub const BIG_STRIDE: usize = usize::MAX / 2;
+
+/// Pick the `Ready` task with the smallest stride.
+pub struct StrideScheduler {
+    ready_tasks: Vec<Arc<TaskControlBlock>>,
+}
+
+/// Whether stride `a` is before stride `b`, taking overflow into account.
+fn stride_before(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_tasks: Vec::new(),
+        }
+    }
+    fn enqueue(&mut self, task: Arc<TaskControlBlock>, _reason: EnqueueReason) {
+        self.ready_tasks.push(task);
+    }
+    fn dequeue(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        if let Some(idx) = self.ready_tasks.iter().position(|t| Arc::ptr_eq(t, task)) {
+            self.ready_tasks.remove(idx);
+            true
+        } else {
+            false
+        }
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let mut next: Option<(usize, usize)> = None;
+        for (idx, task) in self.ready_tasks.iter().enumerate() {
+            let stride = task.inner_exclusive_access().stride;
+            match next {
+                Some((_, min_stride)) if !stride_before(stride, min_stride) => {}
+                _ => next = Some((idx, stride)),
+            }
+        }
+        let (idx, _) = next?;
+        // tasks which keep the same stride are picked in FIFO order
+        let task = self.ready_tasks.remove(idx);
+        let mut inner = task.inner_exclusive_access();
+        inner.stride = inner.stride.wrapping_add(BIG_STRIDE / inner.priority);
+        drop(inner);
+        Some(task)
+    }
+    fn on_tick(&mut self, _current: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+}
@@ -1,11 +1,28 @@
 //! Types related to task management
+use super::init_stack::{linux_init_stack, native_init_stack};
+use super::pid::{pid_alloc, KernelStack, PidHandle};
 use super::TaskContext;
-use crate::config::{kernel_stack_position, TRAP_CONTEXT};
-use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::config::{DEFAULT_PRIORITY, MAX_SYSCALL_NUM, TRAP_CONTEXT};
+use crate::mm::{kernel_token, MemorySet, PhysPageNum, VirtAddr};
+use crate::timer::get_time;
 use crate::trap::{trap_handler, TrapContext};
+use crate::utils::UPSafeCell;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
 
 /// task control block structure
 pub struct TaskControlBlock {
+    // immutable
+    pub pid: PidHandle,
+    pub kernel_stack: KernelStack,
+    // mutable
+    inner: UPSafeCell<TaskControlBlockInner>,
+}
+
+/// mutable part of the task control block, protected by `UPSafeCell`
+pub struct TaskControlBlockInner {
     pub task_status: TaskStatus,
     pub task_cx: TaskContext,
     pub memory_set: MemorySet,
@@ -13,66 +30,235 @@ pub struct TaskControlBlock {
     pub base_size: usize,
     pub heap_bottom: usize,
     pub program_brk: usize,
+    pub parent: Option<Weak<TaskControlBlock>>,
+    pub children: Vec<Arc<TaskControlBlock>>,
+    pub exit_code: i32,
+    /// scheduling priority, at least 2
+    pub priority: usize,
+    /// pass value of the stride scheduler
+    pub stride: usize,
+    /// queue level in the multi-level feedback queue scheduler
+    pub mlfq_level: usize,
+    /// timer ticks used in the current time slice of the mlfq scheduler
+    pub mlfq_ticks: usize,
+    /// number of calls of every syscall, indexed by syscall id
+    pub syscall_times: Vec<u32>,
+    /// `time` register value when the task was first scheduled
+    pub first_scheduled: Option<usize>,
+    /// `time` register value when the current time slice started
+    pub last_scheduled: usize,
+    /// time spent `Running` in all finished time slices, in `time` ticks
+    pub run_time: usize,
+    /// the syscall interface of the task, kept by fork and set by exec
+    pub personality: Personality,
+    /// whether the task is blocked until one of its children exits
+    pub waiting_for_child: bool,
 }
 
-impl TaskControlBlock {
+impl TaskControlBlockInner {
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
     pub fn get_user_token(&self) -> usize {
         self.memory_set.token()
     }
-    pub fn new(elf_data: &[u8], app_id: usize) -> Self {
+    pub fn is_zombie(&self) -> bool {
+        self.task_status == TaskStatus::Zombie
+    }
+    /// mark the task `Running` and record when its time slice starts
+    pub fn start_running(&mut self) {
+        let now = get_time();
+        self.task_status = TaskStatus::Running;
+        self.first_scheduled.get_or_insert(now);
+        self.last_scheduled = now;
+    }
+    /// account the time slice which has just ended
+    pub fn stop_running(&mut self) {
+        self.run_time += get_time() - self.last_scheduled;
+    }
+    /// time spent `Running` including the current time slice, in `time` ticks
+    pub fn total_run_time(&self) -> usize {
+        if self.task_status == TaskStatus::Running {
+            self.run_time + get_time() - self.last_scheduled
+        } else {
+            self.run_time
+        }
+    }
+}
+
+impl TaskControlBlock {
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// None if the inner is in use
+    pub fn try_inner_exclusive_access(&self) -> Option<RefMut<'_, TaskControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+    /// create a task running `elf_data`, return None if there is no memory left
+    pub fn new(elf_data: &[u8]) -> Option<Self> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, elf_info) = MemorySet::from_elf(elf_data)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         let task_status = TaskStatus::Ready;
-        // map a kernel-stack in kernel space
-        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(app_id);
-        KERNEL_SPACE.exclusive_access().insert_framed_area(
-            kernel_stack_bottom.into(),
-            kernel_stack_top.into(),
-            MapPermission::R | MapPermission::W,
-        );
+        // alloc a pid and a kernel stack in kernel space
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle)?;
+        let kernel_stack_top = kernel_stack.get_top();
         let task_control_block = Self {
-            task_status,
-            task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-            memory_set,
-            trap_cx_ppn,
-            base_size: user_sp,
-            heap_bottom: user_sp,
-            program_brk: user_sp,
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    task_status,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    memory_set,
+                    trap_cx_ppn,
+                    base_size: user_sp,
+                    heap_bottom: user_sp,
+                    program_brk: user_sp,
+                    parent: None,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    priority: DEFAULT_PRIORITY,
+                    stride: 0,
+                    mlfq_level: 0,
+                    mlfq_ticks: 0,
+                    syscall_times: vec![0; MAX_SYSCALL_NUM],
+                    first_scheduled: None,
+                    last_scheduled: 0,
+                    run_time: 0,
+                    personality: Personality::Native,
+                    waiting_for_child: false,
+                })
+            },
         };
         // prepare TrapContext in user space
-        let trap_cx = task_control_block.get_trap_cx();
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
-            entry_point,
+            elf_info.entry,
             user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
+            kernel_token(),
             kernel_stack_top,
             trap_handler as usize,
         );
-        task_control_block
+        Some(task_control_block)
+    }
+    /// replace the address space of the task with a new elf, which runs with
+    /// `personality` and gets `args`, keep pid and kernel stack. Return false
+    /// and leave the task as it is if there is no memory for the new space
+    /// or `args` do not fit into the new user stack
+    pub fn exec(&self, elf_data: &[u8], args: &[Vec<u8>], personality: Personality) -> bool {
+        let Some((mut memory_set, user_stack_top, elf_info)) = MemorySet::from_elf(elf_data) else {
+            return false;
+        };
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        let user_sp = match personality {
+            Personality::Native => native_init_stack(&mut memory_set, user_stack_top, args),
+            Personality::Linux => {
+                linux_init_stack(&mut memory_set, user_stack_top, args, &elf_info)
+            }
+        };
+        let Some(user_sp) = user_sp else {
+            return false;
+        };
+        let mut inner = self.inner_exclusive_access();
+        // the old memory_set is dropped here, together with its frames
+        inner.memory_set = memory_set;
+        inner.trap_cx_ppn = trap_cx_ppn;
+        inner.base_size = user_stack_top;
+        inner.heap_bottom = user_stack_top;
+        inner.program_brk = user_stack_top;
+        inner.personality = personality;
+        let trap_cx = inner.get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            elf_info.entry,
+            user_sp,
+            kernel_token(),
+            self.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        // argc and argv of a native program, a Linux one reads them from the stack
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = user_sp;
+        true
+    }
+    /// create a child task with a copy of the parent's address space,
+    /// return None if there is no memory left
+    pub fn fork(self: &Arc<Self>) -> Option<Arc<Self>> {
+        let mut parent_inner = self.inner_exclusive_access();
+        let memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set)?;
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle)?;
+        let kernel_stack_top = kernel_stack.get_top();
+        let task_control_block = Arc::new(Self {
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    task_status: TaskStatus::Ready,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    memory_set,
+                    trap_cx_ppn,
+                    base_size: parent_inner.base_size,
+                    heap_bottom: parent_inner.heap_bottom,
+                    program_brk: parent_inner.program_brk,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    // a child starts where its parent is, so it cannot
+                    // monopolize the cpu to catch up with older tasks
+                    priority: parent_inner.priority,
+                    stride: parent_inner.stride,
+                    mlfq_level: parent_inner.mlfq_level,
+                    mlfq_ticks: 0,
+                    syscall_times: vec![0; MAX_SYSCALL_NUM],
+                    first_scheduled: None,
+                    last_scheduled: 0,
+                    run_time: 0,
+                    personality: parent_inner.personality,
+                    waiting_for_child: false,
+                })
+            },
+        });
+        parent_inner.children.push(task_control_block.clone());
+        // the trap context was copied from the parent, only the kernel stack differs
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        trap_cx.kernel_sp = kernel_stack_top;
+        Some(task_control_block)
     }
     /// change the location of the program break. return None if failed.
-    pub fn change_program_brk(&mut self, size: i32) -> Option<usize> {
-        let old_break = self.program_brk;
-        let new_brk = self.program_brk as isize + size as isize;
-        if new_brk < self.heap_bottom as isize {
+    pub fn change_program_brk(&self, size: i32) -> Option<usize> {
+        let mut inner = self.inner_exclusive_access();
+        let old_break = inner.program_brk;
+        let new_brk = inner.program_brk as isize + size as isize;
+        if new_brk < inner.heap_bottom as isize {
             return None;
         }
+        let heap_bottom = inner.heap_bottom;
         let result = if size < 0 {
-            self.memory_set
-                .shrink_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
+            inner
+                .memory_set
+                .shrink_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
         } else {
-            self.memory_set
-                .append_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
+            inner
+                .memory_set
+                .append_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
         };
         if result {
-            self.program_brk = new_brk as usize;
+            inner.program_brk = new_brk as usize;
             Some(old_break)
         } else {
             None
@@ -81,9 +267,20 @@ impl TaskControlBlock {
 }
 
 #[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+#[repr(usize)]
+/// task status: Ready, Running, Blocked, Zombie
 pub enum TaskStatus {
     Ready,
     Running,
-    Exited,
+    Blocked,
+    Zombie,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// the syscall interface a task sees
+pub enum Personality {
+    /// the syscalls of this kernel
+    Native,
+    /// Linux semantics where they differ, for statically linked musl programs
+    Linux,
 }
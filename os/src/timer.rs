@@ -1,22 +1,153 @@
 //! RISC-V timer-related functionality
 
-use crate::config::CLOCK_FREQ;
+use crate::config::{CLOCK_FREQ, RTC_BASE};
 use crate::sbi::set_timer;
+use crate::task::{wakeup_task, TaskControlBlock};
+use crate::utils::UPSafeCell;
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
+const USEC_PER_SEC: usize = 1_000_000;
+const NSEC_PER_SEC: usize = 1_000_000_000;
+
+/// wall clock time, read from the real time clock
+pub const CLOCK_REALTIME: usize = 0;
+/// time since boot, read from the `time` register
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// time as seconds and microseconds, as used by `gettimeofday`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TimeVal {
+    /// seconds
+    pub sec: usize,
+    /// microseconds, less than one second
+    pub usec: usize,
+}
+
+impl TimeVal {
+    /// split a number of nanoseconds
+    pub fn from_nanos(nanos: usize) -> Self {
+        Self {
+            sec: nanos / NSEC_PER_SEC,
+            usec: nanos % NSEC_PER_SEC / (NSEC_PER_SEC / USEC_PER_SEC),
+        }
+    }
+}
+
+/// time as seconds and nanoseconds, as used by `nanosleep`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TimeSpec {
+    /// seconds
+    pub tv_sec: usize,
+    /// nanoseconds, less than one second
+    pub tv_nsec: usize,
+}
+
+impl TimeSpec {
+    /// split a number of nanoseconds
+    pub fn from_nanos(nanos: usize) -> Self {
+        Self {
+            tv_sec: nanos / NSEC_PER_SEC,
+            tv_nsec: nanos % NSEC_PER_SEC,
+        }
+    }
+    /// convert into a number of `time` register ticks, None if `tv_nsec` is invalid
+    pub fn to_ticks(self) -> Option<usize> {
+        if self.tv_nsec >= NSEC_PER_SEC {
+            return None;
+        }
+        let nsec_ticks = self.tv_nsec * CLOCK_FREQ / NSEC_PER_SEC;
+        self.tv_sec
+            .checked_mul(CLOCK_FREQ)
+            .and_then(|sec_ticks| sec_ticks.checked_add(nsec_ticks))
+    }
+}
 
 pub fn get_time() -> usize {
     time::read()
 }
 
-/// get current time in microseconds
-pub fn get_time_ms() -> usize {
-    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
+/// get time since boot in nanoseconds
+pub fn get_time_ns() -> usize {
+    // split the ticks so that the multiplication cannot overflow
+    let ticks = time::read();
+    ticks / CLOCK_FREQ * NSEC_PER_SEC + ticks % CLOCK_FREQ * NSEC_PER_SEC / CLOCK_FREQ
+}
+
+/// get wall clock time in nanoseconds since the unix epoch
+pub fn get_real_time_ns() -> usize {
+    let rtc = RTC_BASE as *const u32;
+    // reading the low half latches the high half
+    unsafe {
+        let low = rtc.read_volatile() as usize;
+        let high = rtc.add(1).read_volatile() as usize;
+        high << 32 | low
+    }
+}
+
+/// convert a number of `time` register ticks into milliseconds
+pub fn ticks_to_ms(ticks: usize) -> usize {
+    ticks / (CLOCK_FREQ / MSEC_PER_SEC)
 }
 
 /// set the next timer interrupt
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
+
+/// a task blocked until the `time` register reaches `expire`
+pub struct TimerCondVar {
+    pub expire: usize,
+    pub task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire == other.expire
+    }
+}
+impl Eq for TimerCondVar {}
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TimerCondVar {
+    /// reversed, so that `BinaryHeap` pops the earliest timer first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire.cmp(&self.expire)
+    }
+}
+
+lazy_static! {
+    /// min-heap of sleeping tasks, ordered by deadline
+    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
+        unsafe { UPSafeCell::new(BinaryHeap::<TimerCondVar>::new()) };
+}
+
+/// wake up `task` once the `time` register reaches `expire`
+pub fn add_timer(expire: usize, task: Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.push(TimerCondVar { expire, task });
+}
+
+/// wake up all tasks whose deadline has passed, called on every timer interrupt
+pub fn check_timer() {
+    let current = get_time();
+    let mut timers = TIMERS.exclusive_access();
+    while let Some(timer) = timers.peek() {
+        if timer.expire <= current {
+            wakeup_task(timer.task.clone());
+            timers.pop();
+        } else {
+            break;
+        }
+    }
+}
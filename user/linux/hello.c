@@ -0,0 +1,72 @@
+// A statically linked musl program for the Linux personality, built by
+// `make linux` in user/. It goes through the startup code of musl and the
+// syscalls behind stdio, malloc, fork and wait, and exits with 0 if every
+// check passes.
+
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <sys/utsname.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                       \
+    do {                                                                  \
+        if (!(cond)) {                                                    \
+            printf("hello: check failed at line %d: %s\n", __LINE__, #cond); \
+            return 1;                                                     \
+        }                                                                 \
+    } while (0)
+
+int main(int argc, char **argv) {
+    printf("hello from musl, argc = %d\n", argc);
+    for (int i = 0; i < argc; i++) {
+        printf("argv[%d] = %s\n", i, argv[i]);
+    }
+
+    struct utsname uts;
+    CHECK(uname(&uts) == 0);
+    CHECK(strcmp(uts.machine, "riscv64") == 0);
+
+    // small blocks come from brk, large ones from mmap
+    char *small = malloc(100);
+    char *large = malloc(1 << 20);
+    CHECK(small && large);
+    memset(small, 1, 100);
+    memset(large, 2, 1 << 20);
+    CHECK(large[(1 << 20) - 1] == 2);
+    free(large);
+    free(small);
+
+    // the kernel chooses where an anonymous mapping goes
+    char *page = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    CHECK(page != MAP_FAILED);
+    page[0] = 3;
+    CHECK(munmap(page, 4096) == 0);
+    // the file in a4 and the offset in a5 reach the kernel as well, musl itself
+    // would catch an unaligned offset
+    CHECK(syscall(SYS_mmap, 0, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, 3, 0) == -1);
+    CHECK(errno == EBADF);
+    CHECK(syscall(SYS_mmap, 0, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 1) == -1);
+    CHECK(errno == EINVAL);
+
+    CHECK(setpriority(PRIO_PROCESS, 0, -5) == 0);
+
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0) {
+        // skip flushing the stdio buffers copied from the parent
+        _exit(42);
+    }
+    int status;
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 42);
+    CHECK(waitpid(-1, &status, WNOHANG) == -1);
+
+    printf("hello pass.\n");
+    return 0;
+}
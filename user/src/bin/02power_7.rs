@@ -9,7 +9,7 @@ const LEN: usize = 100;
 static mut S: [u64; LEN] = [0u64; LEN];
 
 #[no_mangle]
-unsafe fn main() -> i32 {
+unsafe fn main(_argc: usize, _argv: &[&str]) -> i32 {
     let p = 7u64;
     let m = 998244353u64;
     let iter: usize = 240000;
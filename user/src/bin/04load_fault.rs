@@ -7,7 +7,7 @@ extern crate user_lib;
 use core::ptr::{null_mut, read_volatile};
 
 #[no_mangle]
-fn main() -> i32 {
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
     println!("\nload_fault APP running...\n");
     println!("Into Test load_fault, we will insert an invalid load operation...");
     println!("Kernel should kill this application!");
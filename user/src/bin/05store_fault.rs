@@ -7,7 +7,7 @@ extern crate user_lib;
 use core::ptr::null_mut;
 
 #[no_mangle]
-fn main() -> i32 {
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
     println!("\nstore_fault APP running...\n");
     println!("Into Test store_fault, we will insert an invalid store operation...");
     println!("Kernel should kill this application!");
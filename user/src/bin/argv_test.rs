@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, waitpid, E2BIG, ENAMETOOLONG, MAX_ARGS};
+
+/// longer than the longest path the kernel accepts
+static LONG_PATH: [u8; 4098] = {
+    let mut path = [b'a'; 4098];
+    path[4097] = 0;
+    path
+};
+/// an argument of which two take more than the kernel accepts
+static LONG_ARG: [u8; 3001] = {
+    let mut arg = [b'b'; 3001];
+    arg[3000] = 0;
+    arg
+};
+
+#[no_mangle]
+fn main(argc: usize, argv: &[&str]) -> i32 {
+    assert_eq!(argc, argv.len());
+    assert_eq!(argv[0], "argv_test");
+    if argc > 1 {
+        // run by the parent below, or from the shell with any arguments
+        for (i, arg) in argv.iter().enumerate() {
+            println!("argv[{}] = {}", i, arg);
+        }
+        return argc as i32;
+    }
+
+    let pid = fork().unwrap();
+    if pid == 0 {
+        exec("argv_test\0", &["argv_test\0", "hello\0", "world\0"]).unwrap();
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 3);
+
+    // as many arguments as the kernel and `_start` take
+    let pid = fork().unwrap();
+    if pid == 0 {
+        exec("argv_test\0", &["argv_test\0"; MAX_ARGS]).unwrap();
+    }
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, MAX_ARGS as i32);
+
+    let args = ["argv_test\0"; MAX_ARGS + 1];
+    assert_eq!(exec("argv_test\0", &args), Err(E2BIG));
+    // few arguments, but too long for the kernel
+    let long = core::str::from_utf8(&LONG_ARG).unwrap();
+    assert_eq!(
+        exec("argv_test\0", &["argv_test\0", long, long]),
+        Err(E2BIG)
+    );
+    let path = core::str::from_utf8(&LONG_PATH).unwrap();
+    assert_eq!(exec(path, &[path]), Err(ENAMETOOLONG));
+    println!("argv_test pass.");
+    0
+}
@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, get_time, gettimeofday, sleep, TimeSpec, TimeVal, CLOCK_MONOTONIC,
+    CLOCK_REALTIME, EINVAL,
+};
+
+/// 2020-01-01 00:00:00 UTC
+const EPOCH_2020: usize = 1_577_836_800;
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let mut tv = TimeVal::default();
+    assert_eq!(gettimeofday(&mut tv), Ok(0));
+    assert!(tv.sec >= EPOCH_2020 && tv.usec < 1_000_000);
+    let mut real = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_REALTIME, &mut real), Ok(0));
+    assert!(real.tv_sec >= tv.sec && real.tv_nsec < 1_000_000_000);
+    println!("realtime: {}.{:06}s since the epoch", tv.sec, tv.usec);
+
+    let mut before = TimeSpec::default();
+    let mut after = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut before), Ok(0));
+    let start = get_time();
+    sleep(100).unwrap();
+    assert!(get_time() - start >= 100);
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut after), Ok(0));
+    assert!((after.tv_sec, after.tv_nsec) > (before.tv_sec, before.tv_nsec));
+    println!(
+        "monotonic: {}.{:09}s -> {}.{:09}s",
+        before.tv_sec, before.tv_nsec, after.tv_sec, after.tv_nsec
+    );
+
+    assert_eq!(clock_gettime(42, &mut before), Err(EINVAL));
+    println!("clock_test pass.");
+    0
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, exit, fork, mmap, mprotect, munmap, sbrk, waitpid, TimeSpec, CLOCK_MONOTONIC,
+    EFAULT, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const LEN: usize = 16 * PAGE_SIZE;
+const READ_ONLY: usize = 0x1000_0000;
+
+static mut DATA: [u8; LEN] = [0; LEN];
+static mut TIME: TimeSpec = TimeSpec {
+    tv_sec: 0,
+    tv_nsec: 0,
+};
+
+fn check(data: &[u8], value: u8) {
+    assert!(data.iter().all(|byte| *byte == value));
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let data = unsafe { &mut *core::ptr::addr_of_mut!(DATA) };
+    let time = unsafe { &mut *core::ptr::addr_of_mut!(TIME) };
+    data.fill(1);
+    let heap = sbrk(PAGE_SIZE as i32).unwrap() as *mut u8;
+    unsafe { heap.write_volatile(1) };
+
+    let pid = fork().unwrap();
+    if pid == 0 {
+        // the child sees the parent's memory and its writes stay private
+        check(data, 1);
+        assert_eq!(unsafe { heap.read_volatile() }, 1);
+        data.fill(2);
+        unsafe { heap.write_volatile(2) };
+        // a write from the kernel must not leak into the parent either
+        assert_eq!(clock_gettime(CLOCK_MONOTONIC, time), Ok(0));
+        assert!(time.tv_sec > 0 || time.tv_nsec > 0);
+        check(data, 2);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    check(data, 1);
+    assert_eq!(unsafe { heap.read_volatile() }, 1);
+    assert_eq!((time.tv_sec, time.tv_nsec), (0, 0));
+
+    // the last owner of a shared page writes to it in place
+    data.fill(3);
+    check(data, 3);
+
+    // a shared read-only page is not copied for a write from the kernel
+    assert_eq!(mmap(READ_ONLY, PAGE_SIZE, PROT_READ | PROT_WRITE), Ok(0));
+    let page = unsafe { core::slice::from_raw_parts_mut(READ_ONLY as *mut u8, PAGE_SIZE) };
+    page.fill(4);
+    assert_eq!(mprotect(READ_ONLY, PAGE_SIZE, PROT_READ), Ok(0));
+    let pid = fork().unwrap();
+    if pid == 0 {
+        let time = unsafe { &mut *(READ_ONLY as *mut TimeSpec) };
+        assert_eq!(clock_gettime(CLOCK_MONOTONIC, time), Err(EFAULT));
+        check(page, 4);
+        // made writable again, the page is copied on write instead of shared
+        assert_eq!(
+            mprotect(READ_ONLY, PAGE_SIZE, PROT_READ | PROT_WRITE),
+            Ok(0)
+        );
+        page.fill(5);
+        check(page, 5);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    check(page, 4);
+    assert_eq!(munmap(READ_ONLY, PAGE_SIZE), Ok(0));
+    println!("cow_test pass.");
+    0
+}
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, mprotect, munmap, read, write, EFAULT, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 0x1000;
+const UNMAPPED: usize = 0x1000_0000;
+/// the trap context, which is mapped but not accessible from user mode
+const TRAP_CONTEXT: usize = usize::MAX - 2 * PAGE_SIZE + 1;
+
+fn user_slice(addr: usize, len: usize) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) }
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    assert_eq!(write(1, user_slice(UNMAPPED, 16)), Err(EFAULT));
+    assert_eq!(write(1, user_slice(TRAP_CONTEXT, 16)), Err(EFAULT));
+    // a huge length is checked page by page, not copied into the kernel
+    assert_eq!(write(1, user_slice(UNMAPPED, 1 << 40)), Err(EFAULT));
+
+    // a buffer which crosses into an unmapped page is rejected as a whole
+    assert_eq!(mmap(UNMAPPED, PAGE_SIZE, PROT_READ | PROT_WRITE), Ok(0));
+    let buf = user_slice(UNMAPPED + PAGE_SIZE - 4, 8);
+    assert_eq!(write(1, buf), Err(EFAULT));
+    assert_eq!(write(1, &buf[..4]), Ok(4));
+
+    // the kernel must not write to a read-only page for us
+    assert_eq!(mprotect(UNMAPPED, PAGE_SIZE, PROT_READ), Ok(0));
+    assert_eq!(read(0, user_slice(UNMAPPED, 1)), Err(EFAULT));
+    assert_eq!(munmap(UNMAPPED, PAGE_SIZE), Ok(0));
+    println!("\nefault_test pass.");
+    0
+}
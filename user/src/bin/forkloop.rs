@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, waitpid};
+
+const ROUNDS: usize = 1000;
+/// pids are recycled, so they should stay far below the number of rounds
+const MAX_PID: usize = 100;
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    for _ in 0..ROUNDS {
+        let pid = fork().unwrap();
+        if pid == 0 {
+            exit(0);
+        }
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+        if pid >= MAX_PID {
+            println!("forkloop: got pid {}, pids are not recycled", pid);
+            return -1;
+        }
+    }
+    println!("forkloop: {} children created and reaped", ROUNDS);
+    println!("forkloop pass.");
+    0
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, getpid, wait};
+
+const MAX_CHILD: usize = 10;
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    println!("forktest: parent pid = {}", getpid());
+    for i in 0..MAX_CHILD {
+        let pid = fork().unwrap();
+        if pid == 0 {
+            println!("I am child {}, pid = {}", i, getpid());
+            exit(i as i32);
+        } else {
+            println!("forked child pid = {}", pid);
+        }
+    }
+    let mut exit_code: i32 = 0;
+    for _ in 0..MAX_CHILD {
+        if wait(&mut exit_code).is_err() {
+            panic!("wait stopped early");
+        }
+    }
+    if wait(&mut exit_code).is_ok() {
+        panic!("wait got too many");
+    }
+    println!("forktest pass.");
+    0
+}
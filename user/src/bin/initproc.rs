@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, wait, yield_, ECHILD};
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    if fork() == Ok(0) {
+        exec("user_shell\0", &["user_shell\0"]).unwrap();
+    } else {
+        loop {
+            let mut exit_code: i32 = 0;
+            let pid = match wait(&mut exit_code) {
+                Ok(pid) => pid,
+                Err(ECHILD) => {
+                    yield_();
+                    continue;
+                }
+                Err(err) => panic!("[initproc] wait failed with errno {}", err),
+            };
+            println!(
+                "[initproc] Released a zombie process, pid={}, exit_code={}",
+                pid, exit_code,
+            );
+        }
+    }
+    0
+}
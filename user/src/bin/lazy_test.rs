@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, exit, fork, mmap, munmap, sbrk, waitpid, TimeSpec, CLOCK_MONOTONIC, PROT_READ,
+    PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+/// more than the physical memory, only works if frames are allocated on first touch
+const HUGE: usize = 256 * 1024 * 1024;
+const START: usize = 0x1000_0000;
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let heap = sbrk(HUGE as i32).unwrap();
+    for offset in [0, PAGE_SIZE, HUGE / 2, HUGE - 1] {
+        let ptr = (heap + offset) as *mut u8;
+        unsafe {
+            assert_eq!(ptr.read_volatile(), 0);
+            ptr.write_volatile(0x5a);
+            assert_eq!(ptr.read_volatile(), 0x5a);
+        }
+    }
+    assert_eq!(sbrk(-(HUGE as i32)), Ok(heap + HUGE));
+    println!("sbrk of {:#x} bytes ok", HUGE);
+
+    assert_eq!(mmap(START, HUGE, PROT_READ | PROT_WRITE), Ok(0));
+    // the kernel writes into a page the app never touched
+    let ts = unsafe { &mut *((START + HUGE - PAGE_SIZE) as *mut TimeSpec) };
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, ts), Ok(0));
+    assert!(ts.tv_sec > 0 || ts.tv_nsec > 0);
+    assert_eq!(munmap(START, HUGE), Ok(0));
+    println!("mmap of {:#x} bytes ok", HUGE);
+
+    // writing to a read-only lazy page still kills the app
+    assert_eq!(mmap(START, PAGE_SIZE, PROT_READ), Ok(0));
+    let pid = fork().unwrap();
+    if pid == 0 {
+        unsafe { (START as *mut u8).write_volatile(1) };
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, -2);
+    println!("lazy_test pass.");
+    0
+}
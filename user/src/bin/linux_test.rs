@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, exit, fork, waitpid, ENOENT};
+
+/// exit code of the child when `hello` is not built
+const NOT_BUILT: i32 = 100;
+
+/// Run the musl program `hello` with the Linux personality, it checks the
+/// syscalls it uses by itself and exits with 0.
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        assert_eq!(exec("hello\0", &["hello\0", "linux\0"]), Err(ENOENT));
+        exit(NOT_BUILT);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    if exit_code == NOT_BUILT {
+        println!("hello is not built, run `make linux` in user/ with a musl cross compiler");
+        return 0;
+    }
+    assert_eq!(exit_code, 0);
+    println!("linux_test pass.");
+    0
+}
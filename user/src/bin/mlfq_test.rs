@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, waitpid, yield_};
+
+/// how long the cpu-bound child keeps counting, in ms
+const RUN_TIME: usize = 500;
+/// longest the yielding task may wait for the cpu, in ms, which is the time
+/// slice of the lowest queue of MLFQ plus one tick
+const MAX_GAP: usize = 50;
+
+fn spin() -> ! {
+    let end = get_time() + RUN_TIME;
+    let mut count: usize = 0;
+    while get_time() < end {
+        count += 1;
+    }
+    println!("cpu-bound child counted to {}", count);
+    exit(0);
+    unreachable!();
+}
+
+/// A cpu-bound child runs next to the parent which yields all the time.
+/// The parent should never wait for the cpu longer than a time slice.
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        spin();
+    }
+    let end = get_time() + RUN_TIME / 2;
+    let mut last = get_time();
+    let mut max_gap = 0;
+    let mut rounds: usize = 0;
+    while last < end {
+        yield_();
+        let now = get_time();
+        max_gap = max_gap.max(now - last);
+        last = now;
+        rounds += 1;
+    }
+    println!("yielded {} times, waited at most {} ms", rounds, max_gap);
+    assert!(max_gap <= MAX_GAP);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    println!("mlfq_test done.");
+    0
+}
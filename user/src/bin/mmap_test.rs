@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap, EEXIST, EINVAL, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 0x1000;
+const START: usize = 0x1000_0000;
+
+fn fill(start: usize, len: usize) {
+    let area = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, len) };
+    for (i, byte) in area.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+}
+
+fn check(start: usize, len: usize) {
+    let area = unsafe { core::slice::from_raw_parts(start as *const u8, len) };
+    for (i, byte) in area.iter().enumerate() {
+        assert_eq!(*byte, i as u8);
+    }
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let len = 3 * PAGE_SIZE;
+    assert_eq!(mmap(START, len, PROT_READ | PROT_WRITE), Ok(0));
+    fill(START, len);
+    check(START, len);
+    println!("mmap {:#x} bytes at {:#x} ok", len, START);
+
+    // invalid arguments and overlapping ranges
+    assert_eq!(mmap(START + 1, PAGE_SIZE, PROT_READ), Err(EINVAL));
+    assert_eq!(mmap(START + len, PAGE_SIZE, 0), Err(EINVAL));
+    assert_eq!(mmap(START + len, PAGE_SIZE, 8), Err(EINVAL));
+    assert_eq!(mmap(START + len, 0, PROT_READ), Err(EINVAL));
+    assert_eq!(mmap(START + PAGE_SIZE, PAGE_SIZE, PROT_READ), Err(EEXIST));
+    assert_eq!(
+        mmap(usize::MAX - PAGE_SIZE + 1, PAGE_SIZE, PROT_READ),
+        Err(EINVAL)
+    );
+
+    // unmapping the middle page splits the area in two
+    assert_eq!(munmap(START + PAGE_SIZE, PAGE_SIZE), Ok(0));
+    assert_eq!(munmap(START + PAGE_SIZE, PAGE_SIZE), Err(EINVAL));
+    assert_eq!(munmap(START, len), Err(EINVAL));
+    check(START, PAGE_SIZE);
+    assert_eq!(
+        mmap(START + PAGE_SIZE, PAGE_SIZE, PROT_READ | PROT_WRITE),
+        Ok(0)
+    );
+    let middle = unsafe { core::slice::from_raw_parts(START as *const u8, len) };
+    assert!(middle[PAGE_SIZE..2 * PAGE_SIZE]
+        .iter()
+        .all(|byte| *byte == 0));
+    assert_eq!(munmap(START, len), Ok(0));
+    assert_eq!(munmap(START, PAGE_SIZE), Err(EINVAL));
+    println!("mmap_test pass.");
+    0
+}
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, mmap, mprotect, munmap, sbrk, waitpid, EINVAL, ENOMEM, PROT_EXEC, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const START: usize = 0x1000_0000;
+const LEN: usize = 3 * PAGE_SIZE;
+
+/// `li a0, 42` and `ret`
+const CODE: [u32; 2] = [0x02a0_0513, 0x0000_8067];
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    // write code into a writable page, then make it executable and run it
+    assert_eq!(mmap(START, PAGE_SIZE, PROT_READ | PROT_WRITE), Ok(0));
+    let code = unsafe { core::slice::from_raw_parts_mut(START as *mut u32, CODE.len()) };
+    code.copy_from_slice(&CODE);
+    assert_eq!(mprotect(START, PAGE_SIZE, PROT_READ | PROT_EXEC), Ok(0));
+    let f: fn() -> usize = unsafe { core::mem::transmute(START) };
+    assert_eq!(f(), 42);
+    assert_eq!(munmap(START, PAGE_SIZE), Ok(0));
+    println!("jit code returned 42");
+
+    // protecting the middle page splits the area
+    assert_eq!(mmap(START, LEN, PROT_READ | PROT_WRITE), Ok(0));
+    let data = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, LEN) };
+    data.fill(1);
+    assert_eq!(mprotect(START + PAGE_SIZE, PAGE_SIZE, PROT_READ), Ok(0));
+    assert!(data.iter().all(|byte| *byte == 1));
+    let pid = fork().unwrap();
+    if pid == 0 {
+        data[PAGE_SIZE] = 2;
+        panic!("wrote to a read-only page");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, -2);
+    data[0] = 2;
+    data[2 * PAGE_SIZE] = 2;
+    assert_eq!(
+        mprotect(START + PAGE_SIZE, PAGE_SIZE, PROT_READ | PROT_WRITE),
+        Ok(0)
+    );
+    data[PAGE_SIZE] = 2;
+    println!("read-only page in the middle ok");
+
+    // invalid arguments and unmapped ranges
+    assert_eq!(mprotect(START + 1, PAGE_SIZE, PROT_READ), Err(EINVAL));
+    assert_eq!(mprotect(START, LEN, 0), Err(EINVAL));
+    assert_eq!(mprotect(START, LEN + PAGE_SIZE, PROT_READ), Err(ENOMEM));
+    assert_eq!(munmap(START, LEN), Ok(0));
+    assert_eq!(mprotect(START, PAGE_SIZE, PROT_READ), Err(ENOMEM));
+
+    // the heap can only be moved by sbrk
+    let heap = sbrk(2 * PAGE_SIZE as i32).unwrap();
+    assert_eq!(munmap(heap, PAGE_SIZE), Err(EINVAL));
+    assert_eq!(
+        mprotect(heap + PAGE_SIZE, PAGE_SIZE, PROT_READ),
+        Err(ENOMEM)
+    );
+    let data = unsafe { core::slice::from_raw_parts_mut(heap as *mut u8, 2 * PAGE_SIZE) };
+    data.fill(3);
+    assert_eq!(sbrk(-2 * PAGE_SIZE as i32), Ok(heap + 2 * PAGE_SIZE));
+    assert_eq!(sbrk(0), Ok(heap));
+    println!("mprotect_test pass.");
+    0
+}
@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, shmat, shmctl, shmdt, shmget, waitpid, EINVAL, IPC_PRIVATE, IPC_RMID, SHM_RDONLY,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const LEN: usize = 2 * PAGE_SIZE;
+const KEY: usize = 0x5a5a;
+const FIXED: usize = 0x2000_0000;
+
+fn area(addr: usize) -> &'static mut [u8] {
+    assert!(addr > 0);
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, LEN) }
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    assert_eq!(shmget(IPC_PRIVATE, 0), Err(EINVAL));
+    let id = shmget(IPC_PRIVATE, LEN).unwrap();
+    assert!(id > 0);
+    let shared = area(shmat(id, 0, 0).unwrap());
+    assert!(shared.iter().all(|byte| *byte == 0));
+    shared.fill(1);
+
+    let named = shmget(KEY, PAGE_SIZE).unwrap();
+    assert!(named > 0 && named != id);
+    assert_eq!(shmget(KEY, LEN), Err(EINVAL));
+
+    let pid = fork().unwrap();
+    if pid == 0 {
+        // the attachment is inherited and stays shared instead of copy-on-write
+        assert!(shared.iter().all(|byte| *byte == 1));
+        shared[..PAGE_SIZE].fill(2);
+        // a segment found by its key is the same one the parent attaches
+        assert_eq!(shmget(KEY, PAGE_SIZE), Ok(named));
+        assert_eq!(shmat(named, FIXED, 0), Ok(FIXED));
+        unsafe { (FIXED as *mut u8).write_volatile(3) };
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    assert!(shared[..PAGE_SIZE].iter().all(|byte| *byte == 2));
+    assert!(shared[PAGE_SIZE..].iter().all(|byte| *byte == 1));
+    println!("shm shared with child ok");
+
+    // the named segment outlives the child, which attached it last, until it is removed
+    let persistent = shmat(named, 0, 0).unwrap() as *const u8;
+    assert_eq!(unsafe { persistent.read_volatile() }, 3);
+    assert_eq!(shmctl(named, IPC_RMID), Ok(0));
+    assert_eq!(shmctl(named, IPC_RMID), Err(EINVAL));
+    assert_eq!(shmat(named, 0, 0), Err(EINVAL));
+    // the key no longer finds the removed segment, so a new one is created
+    let renamed = shmget(KEY, PAGE_SIZE).unwrap();
+    assert_ne!(renamed, named);
+    let attached = shmat(renamed, 0, 0).unwrap();
+    assert_eq!(unsafe { (attached as *const u8).read_volatile() }, 0);
+    assert_eq!(shmdt(attached), Ok(0));
+    assert_eq!(shmctl(renamed, 1), Err(EINVAL));
+    assert_eq!(shmctl(renamed, IPC_RMID), Ok(0));
+    // the removed segment is still attached here
+    assert_eq!(unsafe { persistent.read_volatile() }, 3);
+    assert_eq!(shmdt(persistent as usize), Ok(0));
+
+    let readonly = area(shmat(id, 0, SHM_RDONLY).unwrap());
+    assert_ne!(readonly.as_ptr(), shared.as_ptr());
+    assert_eq!(readonly[0], 2);
+    shared[0] = 4;
+    assert_eq!(unsafe { (readonly.as_ptr()).read_volatile() }, 4);
+    assert_eq!(shmat(id, FIXED + 1, 0), Err(EINVAL));
+
+    // a segment nobody has attached stays, even after a failed attachment
+    let fresh = shmget(IPC_PRIVATE, PAGE_SIZE).unwrap();
+    assert_eq!(shmat(fresh, shared.as_ptr() as usize, 0), Err(EINVAL));
+    let attached = shmat(fresh, 0, 0).unwrap();
+    assert_eq!(shmdt(attached), Ok(0));
+    let attached = shmat(fresh, 0, 0).unwrap();
+    assert_eq!(shmdt(attached), Ok(0));
+    assert_eq!(shmctl(fresh, IPC_RMID), Ok(0));
+    assert_eq!(shmat(fresh, 0, 0), Err(EINVAL));
+
+    // removing a segment while attached keeps it until the last detach
+    assert_eq!(shmctl(id, IPC_RMID), Ok(0));
+    shared[0] = 5;
+    assert_eq!(unsafe { (readonly.as_ptr()).read_volatile() }, 5);
+    assert_eq!(shmdt(readonly.as_ptr() as usize), Ok(0));
+    assert_eq!(shmdt(shared.as_ptr() as usize), Ok(0));
+    assert_eq!(shmdt(shared.as_ptr() as usize), Err(EINVAL));
+    assert_eq!(shmat(id, 0, 0), Err(EINVAL));
+    println!("shm_test pass.");
+    0
+}
@@ -0,0 +1,17 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, sleep};
+
+/// The task is blocked by nanosleep and not woken up before its time.
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let wait_for = get_time() + 3000;
+    sleep(3000).unwrap();
+    assert!(get_time() >= wait_for, "sleep woke up too early");
+    println!("sleep_test pass.");
+    0
+}
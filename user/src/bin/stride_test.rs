@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, set_priority, wait, EINVAL};
+
+/// how long every child keeps counting, in ms
+const RUN_TIME: usize = 1000;
+
+fn count_during(prio: isize) -> ! {
+    set_priority(prio).unwrap();
+    let end = get_time() + RUN_TIME;
+    let mut count: usize = 0;
+    loop {
+        count += 1;
+        if count % 1000 == 0 && get_time() >= end {
+            break;
+        }
+    }
+    println!(
+        "priority = {}, count = {}, count / priority = {}",
+        prio,
+        count,
+        count / prio as usize
+    );
+    exit(0);
+    unreachable!();
+}
+
+/// Under the stride scheduler `count / priority` should be roughly the same
+/// for every child, under round robin `count` should.
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    assert_eq!(set_priority(1), Err(EINVAL));
+    for prio in 5..=10 {
+        if fork() == Ok(0) {
+            count_during(prio);
+        }
+    }
+    let mut exit_code: i32 = 0;
+    while wait(&mut exit_code).is_ok() {}
+    println!("stride_test done.");
+    0
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mmap, munmap, shmat, shmget, waitpid, yield_, IPC_PRIVATE, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+/// more than the physical memory of the qemu virt machine
+const LEN: usize = 144 * 1024 * 1024;
+const START: usize = 0x1000_0000;
+/// what the child maps after the parent has filled the memory
+const CHILD_LEN: usize = 8 * 1024 * 1024;
+const CHILD_START: usize = 0x2000_0000;
+
+fn page(i: usize) -> *mut usize {
+    (START + i * PAGE_SIZE) as *mut usize
+}
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    // the child starts once the parent's pages fill the memory, then it needs
+    // frames for its pages and page tables which only the parent can give
+    let flag = shmat(shmget(IPC_PRIVATE, PAGE_SIZE).unwrap(), 0, 0).unwrap() as *mut usize;
+    let pid = fork().unwrap();
+    if pid == 0 {
+        while unsafe { flag.read_volatile() } == 0 {
+            yield_();
+        }
+        assert_eq!(mmap(CHILD_START, CHILD_LEN, PROT_READ | PROT_WRITE), Ok(0));
+        let data = unsafe { core::slice::from_raw_parts_mut(CHILD_START as *mut u8, CHILD_LEN) };
+        data.fill(1);
+        assert!(data.iter().all(|byte| *byte == 1));
+        exit(0);
+    }
+    assert_eq!(mmap(START, LEN, PROT_READ | PROT_WRITE), Ok(0));
+    let pages = LEN / PAGE_SIZE;
+    for i in 0..pages {
+        unsafe { page(i).write_volatile(i) };
+    }
+    println!("wrote {} pages", pages);
+    unsafe { flag.write_volatile(1) };
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    println!("the child got memory from the parent");
+    // twice, so that pages come back from the swap area and go out again
+    for _ in 0..2 {
+        for i in 0..pages {
+            assert_eq!(unsafe { page(i).read_volatile() }, i);
+        }
+    }
+    assert_eq!(munmap(START, LEN), Ok(0));
+    println!("swap_test pass.");
+    0
+}
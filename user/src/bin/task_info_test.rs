@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, getpid, task_info, waitpid, yield_, TaskInfo, TaskStatus, ESRCH,
+};
+
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_YIELD: usize = 124;
+
+#[no_mangle]
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    let start = get_time();
+    for _ in 0..3 {
+        getpid();
+        yield_();
+    }
+    let mut info = TaskInfo::new();
+    assert_eq!(task_info(-1, &mut info), Ok(0));
+    assert_eq!(info.status, TaskStatus::Running);
+    assert_eq!(info.syscall_times[SYSCALL_GETPID], 3);
+    assert_eq!(info.syscall_times[SYSCALL_YIELD], 3);
+    assert!(info.first_scheduled <= start);
+    println!(
+        "self: first scheduled at {}ms, ran for {}ms",
+        info.first_scheduled, info.run_time
+    );
+
+    let pid = fork().unwrap();
+    if pid == 0 {
+        exit(7);
+    }
+    // wait until the child has exited, without reaping it
+    while task_info(pid as isize, &mut info).is_ok() && info.status != TaskStatus::Zombie {
+        yield_();
+    }
+    assert_eq!(info.status, TaskStatus::Zombie);
+    println!("child {}: ran for {}ms", pid, info.run_time);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(task_info(pid as isize, &mut info), Err(ESRCH));
+    println!("task_info_test pass.");
+    0
+}
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+const LF: u8 = 0x0au8;
+const CR: u8 = 0x0du8;
+const DL: u8 = 0x7fu8;
+const BS: u8 = 0x08u8;
+
+/// longest command line the shell accepts, including the trailing `\0`
+const LINE_MAX: usize = 128;
+
+use user_lib::console::getchar;
+use user_lib::{exec, fork, waitpid, MAX_ARGS};
+
+#[no_mangle]
+pub fn main(_argc: usize, _argv: &[&str]) -> i32 {
+    println!("Rust user shell");
+    let mut line = [0u8; LINE_MAX];
+    let mut len: usize = 0;
+    print!(">> ");
+    loop {
+        let c = getchar();
+        match c {
+            LF | CR => {
+                println!("");
+                // split the line into nul-terminated arguments in place
+                line[len] = b'\0';
+                for byte in line[..len].iter_mut() {
+                    if *byte == b' ' {
+                        *byte = b'\0';
+                    }
+                }
+                let mut args = [""; MAX_ARGS];
+                let mut argc = 0;
+                let mut start = 0;
+                for end in 0..=len {
+                    if line[end] != b'\0' {
+                        continue;
+                    }
+                    if end > start {
+                        if argc == MAX_ARGS {
+                            println!("Too many arguments!");
+                            argc = 0;
+                            break;
+                        }
+                        // `line` only ever holds printable ascii, see below
+                        args[argc] = core::str::from_utf8(&line[start..=end]).unwrap();
+                        argc += 1;
+                    }
+                    start = end + 1;
+                }
+                if argc > 0 {
+                    let pid = fork().unwrap();
+                    if pid == 0 {
+                        // child process, exec only returns if it fails
+                        let err = exec(args[0], &args[..argc]).unwrap_err();
+                        println!("Error when executing! errno = {}", err);
+                        return -4;
+                    } else {
+                        let mut exit_code: i32 = 0;
+                        assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                    }
+                }
+                len = 0;
+                print!(">> ");
+            }
+            BS | DL => {
+                if len > 0 {
+                    print!("{}", BS as char);
+                    print!(" ");
+                    print!("{}", BS as char);
+                    len -= 1;
+                }
+            }
+            // keep one byte for the `\0`
+            0x20..=0x7e if len < LINE_MAX - 1 => {
+                print!("{}", c as char);
+                line[len] = c;
+                len += 1;
+            }
+            _ => {}
+        }
+    }
+}
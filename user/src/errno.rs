@@ -0,0 +1,40 @@
+//! Error numbers of syscalls, the same as those of the kernel and Linux
+
+/// no such file or directory
+pub const ENOENT: isize = 2;
+/// no such process
+pub const ESRCH: isize = 3;
+/// argument list too long
+pub const E2BIG: isize = 7;
+/// bad file descriptor
+pub const EBADF: isize = 9;
+/// no child processes
+pub const ECHILD: isize = 10;
+/// try again
+pub const EAGAIN: isize = 11;
+/// out of memory
+pub const ENOMEM: isize = 12;
+/// bad address
+pub const EFAULT: isize = 14;
+/// already exists
+pub const EEXIST: isize = 17;
+/// invalid argument
+pub const EINVAL: isize = 22;
+/// not a terminal
+pub const ENOTTY: isize = 25;
+/// file name too long
+pub const ENAMETOOLONG: isize = 36;
+/// function not implemented
+pub const ENOSYS: isize = 38;
+
+/// the result of a syscall, a non-negative value or one of the errnos above
+pub type SysResult = Result<usize, isize>;
+
+/// the kernel returns an error as its negated errno
+pub(crate) fn to_result(ret: isize) -> SysResult {
+    if ret < 0 {
+        Err(-ret)
+    } else {
+        Ok(ret as usize)
+    }
+}
@@ -4,37 +4,240 @@
 
 #[macro_use]
 pub mod console;
+mod errno;
 mod lang_items;
 mod syscall;
 
+pub use errno::*;
+
+/// most arguments a program can take, the kernel returns E2BIG for more
+pub const MAX_ARGS: usize = 32;
+
+/// the nul-terminated string at `ptr`, without the `\0`. The kernel passes any bytes,
+/// so a string which is not valid UTF-8 is cut before its first invalid byte.
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while ptr.add(len).read() != 0 {
+        len += 1;
+    }
+    let bytes = core::slice::from_raw_parts(ptr, len);
+    core::str::from_utf8(bytes)
+        .unwrap_or_else(|err| core::str::from_utf8_unchecked(&bytes[..err.valid_up_to()]))
+}
+
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
-    exit(main());
+pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+    assert!(argc <= MAX_ARGS, "too many arguments");
+    let argv = argv as *const *const u8;
+    let mut args = [""; MAX_ARGS];
+    for (i, arg) in args[..argc].iter_mut().enumerate() {
+        *arg = unsafe { c_str(argv.add(i).read()) };
+    }
+    exit(main(argc, &args[..argc]));
     panic!("unreachable after sys_exit!");
 }
 
 #[linkage = "weak"]
 #[no_mangle]
-fn main() -> i32 {
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
     panic!("Cannot find main!");
 }
 
 use syscall::*;
 
-pub fn write(fd: usize, buf: &[u8]) -> isize {
-    sys_write(fd, buf)
+/// syscall ids below this are counted per task
+pub const MAX_SYSCALL_NUM: usize = 500;
+
+/// status of a task, as reported by `task_info`
+#[repr(usize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TaskStatus {
+    Ready,
+    Running,
+    Blocked,
+    Zombie,
+}
+
+/// information about a task, filled by `task_info`
+#[repr(C)]
+#[derive(Debug)]
+pub struct TaskInfo {
+    pub status: TaskStatus,
+    /// number of calls of every syscall, indexed by syscall id
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    /// time when the task was first scheduled in ms, 0 if it never ran
+    pub first_scheduled: usize,
+    /// total time the task has been running in ms
+    pub run_time: usize,
+}
+
+impl TaskInfo {
+    pub fn new() -> Self {
+        TaskInfo {
+            status: TaskStatus::Ready,
+            syscall_times: [0; MAX_SYSCALL_NUM],
+            first_scheduled: 0,
+            run_time: 0,
+        }
+    }
+}
+
+impl Default for TaskInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub const PROT_READ: usize = 1;
+pub const PROT_WRITE: usize = 2;
+pub const PROT_EXEC: usize = 4;
+
+/// the `shmget` key which always creates a new segment
+pub const IPC_PRIVATE: usize = 0;
+/// the `shmat` flag which attaches a segment read-only
+pub const SHM_RDONLY: usize = 0o10000;
+/// the `shmctl` command which removes a segment once it is detached everywhere
+pub const IPC_RMID: usize = 0;
+
+/// wall clock time, for `clock_gettime`
+pub const CLOCK_REALTIME: usize = 0;
+/// time since boot, for `clock_gettime`
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// time as seconds and microseconds, as used by `gettimeofday`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TimeVal {
+    pub sec: usize,
+    pub usec: usize,
+}
+
+/// time as seconds and nanoseconds, as used by `nanosleep`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+pub fn read(fd: usize, buf: &mut [u8]) -> SysResult {
+    to_result(sys_read(fd, buf))
+}
+pub fn write(fd: usize, buf: &[u8]) -> SysResult {
+    to_result(sys_write(fd, buf))
 }
 pub fn exit(exit_code: i32) -> isize {
     sys_exit(exit_code)
 }
-pub fn yield_() -> isize {
-    sys_yield()
+/// block for the duration in `req`
+pub fn nanosleep(req: &TimeSpec) -> SysResult {
+    to_result(sys_nanosleep(req, core::ptr::null_mut()))
+}
+/// block for `period_ms` milliseconds
+pub fn sleep(period_ms: usize) -> SysResult {
+    let req = TimeSpec {
+        tv_sec: period_ms / 1000,
+        tv_nsec: period_ms % 1000 * 1_000_000,
+    };
+    nanosleep(&req)
+}
+pub fn yield_() {
+    sys_yield();
+}
+/// set the priority of current task, `EINVAL` if `prio` is less than 2
+pub fn set_priority(prio: isize) -> SysResult {
+    to_result(sys_set_priority(prio))
+}
+/// get wall clock time since the unix epoch
+pub fn gettimeofday(tv: &mut TimeVal) -> SysResult {
+    to_result(sys_get_time(tv, 0))
 }
-pub fn get_time() -> isize {
-    sys_get_time()
+/// get the time of `clock_id`, `EINVAL` if the clock is not supported
+pub fn clock_gettime(clock_id: usize, tp: &mut TimeSpec) -> SysResult {
+    to_result(sys_clock_gettime(clock_id, tp))
+}
+/// get time since boot in milliseconds
+pub fn get_time() -> usize {
+    let mut tp = TimeSpec::default();
+    clock_gettime(CLOCK_MONOTONIC, &mut tp).unwrap();
+    tp.tv_sec * 1000 + tp.tv_nsec / 1_000_000
 }
 
-pub fn sbrk(size: i32) -> isize {
-    sys_sbrk(size)
+/// move the end of the heap by `size` bytes, return the old end
+pub fn sbrk(size: i32) -> SysResult {
+    to_result(sys_sbrk(size))
+}
+/// map `len` bytes of zeroed memory at the page aligned `start`, `prot` is a
+/// combination of `PROT_READ`, `PROT_WRITE` and `PROT_EXEC`
+pub fn mmap(start: usize, len: usize, prot: usize) -> SysResult {
+    to_result(sys_mmap(start, len, prot))
+}
+pub fn munmap(start: usize, len: usize) -> SysResult {
+    to_result(sys_munmap(start, len))
+}
+/// change the permission of `len` bytes at the page aligned `start` to `prot`
+pub fn mprotect(start: usize, len: usize, prot: usize) -> SysResult {
+    to_result(sys_mprotect(start, len, prot))
+}
+/// get the id of the shared memory segment with `key`, creating one of at least
+/// `size` bytes if there is none or `key` is `IPC_PRIVATE`
+pub fn shmget(key: usize, size: usize) -> SysResult {
+    to_result(sys_shmget(key, size))
+}
+/// control a shared memory segment, only `IPC_RMID` is supported
+pub fn shmctl(shmid: usize, cmd: usize) -> SysResult {
+    to_result(sys_shmctl(shmid, cmd))
+}
+/// attach a shared memory segment at `addr`, or where there is room if it is 0,
+/// return the address it is attached at
+pub fn shmat(shmid: usize, addr: usize, shmflg: usize) -> SysResult {
+    to_result(sys_shmat(shmid, addr, shmflg))
+}
+/// detach the shared memory segment attached at `addr`
+pub fn shmdt(addr: usize) -> SysResult {
+    to_result(sys_shmdt(addr))
+}
+pub fn getpid() -> usize {
+    sys_getpid() as usize
+}
+/// return 0 in the child and the child's pid in the parent
+pub fn fork() -> SysResult {
+    to_result(sys_fork())
+}
+/// run the app `path` with `args`, whose first one is usually `path`.
+/// `path` and every one of `args` must end with a `\0`, only returns if it fails
+pub fn exec(path: &str, args: &[&str]) -> SysResult {
+    if args.len() > MAX_ARGS {
+        return Err(E2BIG);
+    }
+    let mut argv = [core::ptr::null(); MAX_ARGS + 1];
+    for (ptr, arg) in argv.iter_mut().zip(args) {
+        assert!(arg.ends_with('\0'));
+        *ptr = arg.as_ptr();
+    }
+    to_result(sys_exec(path, &argv))
+}
+/// wait for any child to exit, yielding while none has exited yet,
+/// `ECHILD` if there is no child
+pub fn wait(exit_code: &mut i32) -> SysResult {
+    loop {
+        match to_result(sys_waitpid(-1, exit_code as *mut _)) {
+            Err(EAGAIN) => yield_(),
+            result => return result,
+        }
+    }
+}
+/// wait for the child with `pid` to exit, yielding while it is still running
+pub fn waitpid(pid: usize, exit_code: &mut i32) -> SysResult {
+    loop {
+        match to_result(sys_waitpid(pid as isize, exit_code as *mut _)) {
+            Err(EAGAIN) => yield_(),
+            result => return result,
+        }
+    }
+}
+/// get information about the task with `pid`, or the calling task if `pid` is negative
+pub fn task_info(pid: isize, info: &mut TaskInfo) -> SysResult {
+    to_result(sys_task_info(pid, info))
 }
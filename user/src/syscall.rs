@@ -1,41 +1,142 @@
+use super::{TaskInfo, TimeSpec, TimeVal};
 use core::arch::asm;
 
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
 const SYSCALL_SBRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_FORK: usize = 220;
+const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_TASK_INFO: usize = 410;
 
-fn syscall(id: usize, args: [usize; 3]) -> isize {
+/// the number of arguments of a syscall, checked when `syscall` is compiled
+struct Args<const N: usize>;
+
+impl<const N: usize> Args<N> {
+    const AT_MOST_SIX: () = assert!(N <= 6, "a syscall takes at most 6 arguments");
+}
+
+/// pass `args` in a0-a5, the registers after the given arguments are 0
+fn syscall<const N: usize>(id: usize, args: [usize; N]) -> isize {
+    let () = Args::<N>::AT_MOST_SIX;
+    let mut regs = [0usize; 6];
+    regs[..N].copy_from_slice(&args);
     let mut ret: isize;
     unsafe {
         asm!(
             "ecall",
-            inlateout("x10") args[0] => ret,
-            in("x11") args[1],
-            in("x12") args[2],
+            inlateout("x10") regs[0] => ret,
+            in("x11") regs[1],
+            in("x12") regs[2],
+            in("x13") regs[3],
+            in("x14") regs[4],
+            in("x15") regs[5],
             in("x17") id
         );
     }
     ret
 }
 
+pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_READ,
+        [fd, buffer.as_mut_ptr() as usize, buffer.len()],
+    )
+}
+
 pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
 pub fn sys_exit(exit_code: i32) -> isize {
-    syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0])
+    syscall(SYSCALL_EXIT, [exit_code as usize])
+}
+
+pub fn sys_nanosleep(req: &TimeSpec, rem: *mut TimeSpec) -> isize {
+    syscall(SYSCALL_NANOSLEEP, [req as *const _ as usize, rem as usize])
+}
+
+pub fn sys_clock_gettime(clock_id: usize, tp: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock_id, tp as *mut _ as usize])
 }
 
 pub fn sys_yield() -> isize {
-    syscall(SYSCALL_YIELD, [0, 0, 0])
+    syscall(SYSCALL_YIELD, [])
 }
 
-pub fn sys_get_time() -> isize {
-    syscall(SYSCALL_GET_TIME, [0, 0, 0])
+pub fn sys_set_priority(prio: isize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [prio as usize])
+}
+
+pub fn sys_get_time(ts: &mut TimeVal, tz: usize) -> isize {
+    syscall(SYSCALL_GET_TIME, [ts as *mut _ as usize, tz])
 }
 
 pub fn sys_sbrk(size: i32) -> isize {
-    syscall(SYSCALL_SBRK, [size as usize, 0, 0])
+    syscall(SYSCALL_SBRK, [size as usize])
+}
+
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MMAP, [start, len, prot])
+}
+
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [start, len])
+}
+
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_shmget(key: usize, size: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size])
+}
+
+pub fn sys_shmctl(shmid: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [shmid, cmd, 0])
+}
+
+pub fn sys_shmat(shmid: usize, addr: usize, shmflg: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [shmid, addr, shmflg])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr])
+}
+
+pub fn sys_getpid() -> isize {
+    syscall(SYSCALL_GETPID, [])
+}
+
+pub fn sys_fork() -> isize {
+    syscall(SYSCALL_FORK, [])
+}
+
+pub fn sys_exec(path: &str, argv: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_EXEC,
+        [path.as_ptr() as usize, argv.as_ptr() as usize],
+    )
+}
+
+pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize])
+}
+
+pub fn sys_task_info(pid: isize, info: &mut TaskInfo) -> isize {
+    syscall(SYSCALL_TASK_INFO, [pid as usize, info as *mut _ as usize])
 }